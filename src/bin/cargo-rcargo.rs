@@ -0,0 +1,7 @@
+// `cargo-rcargo` binary, run by cargo for `cargo rcargo ...`.
+//
+// It is the same program as `rcargo`; argv0 detection in `rcargo::run` takes
+// care of the argument cargo injects when running external subcommands.
+fn main() {
+    rcargo::run();
+}
@@ -0,0 +1,156 @@
+use crate::lock::lock_for_purge;
+use crate::registry::{ProjectRecord, Registry};
+use crate::roots::{routed_profile_dirs, select_root};
+use crate::utils::{
+    ResolvedProject, calculate_directory_size, create_target_symlink, format_size, get_cargo_path,
+    get_target_link_name, move_directory, resolve_project,
+};
+use std::fs;
+
+/// Name of the directory cargo builds into when the target dir is not redirected.
+const IN_TREE_TARGET_NAME: &str = "target";
+
+/// Handles the adopt command, moving an in-tree `target` directory into the cache.
+pub fn handle_adopt_command(dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let project = resolve_project(&get_cargo_path())?;
+    let source = project.path.join(IN_TREE_TARGET_NAME);
+
+    match fs::symlink_metadata(&source) {
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            println!(
+                "'{}' is already a symlink, nothing to adopt",
+                source.display()
+            );
+            return reenable_redirection(&project, dry_run, |_| {});
+        }
+        Ok(metadata) if metadata.is_dir() => {}
+        _ => {
+            println!(
+                "Project '{}' has no in-tree target directory to adopt",
+                project.identifier.name()
+            );
+            return reenable_redirection(&project, dry_run, |_| {});
+        }
+    }
+
+    // Plain cargo builds the dev profile by default, and the size of the
+    // directory stands in for that of a cache the project never had
+    let size = calculate_directory_size(&source)?;
+    let mut record = Registry::load_or_default()
+        .get(&project.identifier)
+        .cloned()
+        .unwrap_or_default();
+    record.last_size = Some(size);
+    let root = select_root(&project, "dev", Some(&record))?;
+    let target_path = root.root.path.clone();
+    let cache_dir = root.root.cache_dir(&project.identifier);
+
+    // Refuse to merge into an existing cache, an empty leftover is fine
+    if cache_dir.exists() {
+        let is_empty = fs::read_dir(&cache_dir)?.next().is_none();
+        if !is_empty {
+            return Err(format!(
+                "Project '{}' already has a cached target directory at '{}'; purge it first",
+                project.identifier.name(),
+                cache_dir.display()
+            )
+            .into());
+        }
+    }
+
+    let size_str = format_size(size);
+    let splits = routed_profile_dirs(&source, &root.root)?;
+
+    if dry_run {
+        println!(
+            "Would move '{}' ({}) to '{}'",
+            source.display(),
+            size_str,
+            cache_dir.display()
+        );
+        println!(
+            "Would link '{}' -> '{}'",
+            project.path.join(get_target_link_name()).display(),
+            cache_dir.display()
+        );
+        for split in &splits {
+            println!("Would move {}", split);
+        }
+        return Ok(());
+    }
+
+    // Plain cargo may still be building into the in-tree directory
+    let _lock = match lock_for_purge(&source, false)? {
+        Ok(lock) => lock,
+        Err(busy) => {
+            return Err(format!(
+                "'{}' is in use: {}; try again once the build is done",
+                source.display(),
+                busy
+            )
+            .into());
+        }
+    };
+
+    if cache_dir.exists() {
+        fs::remove_dir(&cache_dir)?;
+    }
+    fs::create_dir_all(&target_path)?;
+
+    let method = move_directory(&source, &cache_dir)?;
+    println!(
+        "Adopted '{}' into '{}' ({}, {})",
+        source.display(),
+        cache_dir.display(),
+        method,
+        size_str
+    );
+
+    for split in &splits {
+        split.link(&cache_dir, &project.identifier)?;
+        println!("Moved {}", split);
+    }
+
+    if let Err(e) = create_target_symlink(&project.path, &cache_dir) {
+        eprintln!("Warning: Could not create target symlink: {}", e);
+    }
+    reenable_redirection(&project, dry_run, |record| {
+        record.track_link(&project.path, &cache_dir);
+        record
+            .cache_roots
+            .insert("dev".to_string(), root.root.name.clone());
+    })?;
+
+    if get_target_link_name() != IN_TREE_TARGET_NAME {
+        println!(
+            "Note: plain cargo will build into a fresh '{}'; set RCARGO_TARGET_LINK_NAME=target to share the cache",
+            IN_TREE_TARGET_NAME
+        );
+    }
+
+    Ok(())
+}
+
+/// Records the project, opting it back into redirection if it was ejected,
+/// once there is nothing left that could fail.
+fn reenable_redirection(
+    project: &ResolvedProject,
+    dry_run: bool,
+    update: impl FnOnce(&mut ProjectRecord),
+) -> Result<(), Box<dyn std::error::Error>> {
+    if dry_run {
+        return Ok(());
+    }
+    let reenabled = Registry::update(|registry| {
+        let record = registry.record_mut(&project.identifier, &project.path);
+        update(record);
+        std::mem::replace(&mut record.opted_out, false)
+    })?;
+    if reenabled {
+        println!(
+            "Re-enabled redirection for project '{}'",
+            project.identifier.name()
+        );
+    }
+    Ok(())
+}
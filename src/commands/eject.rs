@@ -0,0 +1,150 @@
+use crate::lock::{CACHE_LOCK_NAME, lock_for_purge, remove_if_emptied};
+use crate::registry::Registry;
+use crate::roots::{linked_profile_dirs, project_caches};
+use crate::utils::{
+    TargetLinkState, calculate_directory_size, copy_directory, format_size, get_cargo_path,
+    get_target_link_name, inspect_target_symlink, move_directory, resolve_project,
+};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// Handles the eject command, moving a project's cache back in-tree and
+/// opting the project out of redirection.
+pub fn handle_eject_command(
+    to: Option<PathBuf>,
+    copy: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let project = resolve_project(&get_cargo_path())?;
+    let destination = to.unwrap_or_else(|| project.path.join("target"));
+
+    // The cache the link points at is the one cargo last built into
+    let mut caches = project_caches(&project.identifier)?;
+    let linked = caches.iter().position(|cache| {
+        inspect_target_symlink(&project.path, &cache.path) == TargetLinkState::Ok
+    });
+    let cache = match linked {
+        Some(index) => Some(caches.remove(index)),
+        None if caches.is_empty() => None,
+        None => Some(caches.remove(0)),
+    };
+
+    // The rcargo symlink may be sitting at the destination, and goes away
+    // only once the cache can actually be moved there
+    let link_path = project.path.join(get_target_link_name());
+    let is_link_destination = linked.is_some() && destination == link_path;
+    if cache.is_some() && !is_link_destination && fs::symlink_metadata(&destination).is_ok() {
+        return Err(format!(
+            "'{}' already exists; remove it or choose another destination with --to",
+            destination.display()
+        )
+        .into());
+    }
+
+    // Keep builds out of every cache of the project until it is moved; the
+    // other roots hold the linked profile directories
+    let mut locks = Vec::new();
+    for cache_dir in cache.iter().chain(&caches).map(|cache| &cache.path) {
+        match lock_for_purge(cache_dir, false)? {
+            Ok(lock) => locks.push(lock),
+            Err(busy) => {
+                return Err(format!(
+                    "Project '{}' cache is in use: {}; try again once the build is done",
+                    project.identifier.name(),
+                    busy
+                )
+                .into());
+            }
+        }
+    }
+
+    // Profile directories kept in other roots come along as real
+    // directories, the symlinks would dangle once the caches go
+    let cache = match cache {
+        Some(cache) => {
+            let linked_profiles = linked_profile_dirs(&cache.path)?;
+            let mut size = calculate_directory_size(&cache.path)?;
+            for linked in &linked_profiles {
+                size += calculate_directory_size(&linked.target)?;
+            }
+            Some((cache.path, linked_profiles, size))
+        }
+        None => None,
+    };
+
+    if linked.is_some() {
+        fs::remove_file(&link_path)?;
+        println!("Removed symlink '{}'", link_path.display());
+    }
+
+    if let Some((cache_dir, linked_profiles, size)) = cache {
+        let size_str = format_size(size);
+        if copy {
+            copy_directory(&cache_dir, &destination)?;
+            println!(
+                "Copied project '{}' cache to '{}' ({})",
+                project.identifier.name(),
+                destination.display(),
+                size_str
+            );
+        } else {
+            let method = move_directory(&cache_dir, &destination)?;
+            println!(
+                "Ejected project '{}' cache to '{}' ({}, {})",
+                project.identifier.name(),
+                destination.display(),
+                method,
+                size_str
+            );
+        }
+
+        for linked in &linked_profiles {
+            let profile_dir = destination.join(&linked.profile_dir);
+            fs::remove_file(&profile_dir)?;
+            if copy {
+                copy_directory(&linked.target, &profile_dir)?;
+            } else {
+                move_directory(&linked.target, &profile_dir)?;
+            }
+            println!(
+                "Brought '{}' in from '{}'",
+                linked.profile_dir.display(),
+                linked.target.display()
+            );
+        }
+        // rcargo's lock file means nothing to plain cargo
+        match fs::remove_file(destination.join(CACHE_LOCK_NAME)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        if !copy {
+            // Caches that only held the linked profiles are empty now
+            caches.retain(|cache| !remove_if_emptied(&cache.path).unwrap_or(false));
+        }
+    } else {
+        println!(
+            "Current project '{}' has no cached target directory to eject",
+            project.identifier.name()
+        );
+    }
+
+    for cache in &caches {
+        eprintln!(
+            "Warning: Project '{}' also has a cache in root {}; remove it with `rcargo purge`",
+            project.identifier.name(),
+            cache.root
+        );
+    }
+
+    Registry::update(|registry| {
+        let record = registry.record_mut(&project.identifier, &project.path);
+        record.opted_out = true;
+        record.links.remove(&link_path);
+    })?;
+    println!(
+        "Builds of '{}' will no longer be redirected; run `rcargo adopt` to opt back in",
+        project.identifier.name()
+    );
+
+    Ok(())
+}
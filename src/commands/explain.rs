@@ -0,0 +1,73 @@
+use crate::plan::ExecutionPlan;
+use crate::utils::{get_cargo_path, get_target_link_name, inspect_target_symlink, shell_quote};
+
+/// Handles the explain command, showing how cargo would be run without running it.
+pub fn handle_explain_command(cargo_args: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let plan = ExecutionPlan::new(get_cargo_path(), cargo_args)?;
+
+    let decision = if plan.redirect.is_some() {
+        "redirected"
+    } else {
+        "passed through"
+    };
+    println!("Decision:       {} ({})", decision, plan.reason());
+
+    if let Some(redirect) = &plan.redirect {
+        let root_note = if redirect.project.from_cargo_metadata {
+            ""
+        } else {
+            " (cargo metadata unavailable, using current directory)"
+        };
+        println!(
+            "Workspace root: {}{}",
+            redirect.project.path.display(),
+            root_note
+        );
+        println!(
+            "Identifier:     {}",
+            redirect.project.identifier.identifier()
+        );
+
+        println!("Target root:    {}", redirect.root);
+        if let Some(split) = &redirect.split {
+            println!("Linked profile: {}", split);
+        }
+        if let Some(demoted) = &redirect.demoted {
+            println!("Demoted cache:  in {}, would be moved back", demoted);
+        }
+
+        let exists_note = if redirect.cargo_target_dir.is_dir() {
+            "exists"
+        } else {
+            "would be created"
+        };
+        println!(
+            "Target dir:     {} ({})",
+            redirect.cargo_target_dir.display(),
+            exists_note
+        );
+        println!(
+            "Target link:    {} ({})",
+            redirect.project.path.join(get_target_link_name()).display(),
+            inspect_target_symlink(&redirect.project.path, &redirect.cargo_target_dir)
+        );
+    }
+
+    let env = plan.env();
+    if env.is_empty() {
+        println!("Environment:    unchanged");
+    } else {
+        println!("Environment:");
+        for (name, value) in &env {
+            println!("  {}={}", name, shell_quote(&value.to_string_lossy()));
+        }
+    }
+
+    let command_line: Vec<String> = std::iter::once(plan.cargo_path.as_str())
+        .chain(plan.args.iter().map(String::as_str))
+        .map(shell_quote)
+        .collect();
+    println!("Command:        {}", command_line.join(" "));
+
+    Ok(())
+}
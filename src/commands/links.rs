@@ -0,0 +1,172 @@
+use crate::registry::Registry;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of cargo's default target directory; a link with this name must never
+/// dangle, or plain cargo fails to build the project.
+const CARGO_TARGET_NAME: &str = "target";
+
+/// State of a symlink rcargo created in a project root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TrackedLinkState {
+    /// The link points at an existing cache directory.
+    Ok(PathBuf),
+    /// The link points at a cache directory that no longer exists.
+    Dangling(PathBuf),
+    /// The path is no longer an rcargo symlink (removed or replaced).
+    Gone,
+}
+
+/// Inspects a tracked link, only treating it as rcargo's if it still points at
+/// a cache directory named after the project identifier.
+fn inspect_tracked_link(link: &Path, identifier: &str) -> TrackedLinkState {
+    let is_symlink = fs::symlink_metadata(link).is_ok_and(|metadata| metadata.is_symlink());
+    let link_target = match fs::read_link(link) {
+        Ok(link_target) if is_symlink => link_target,
+        _ => return TrackedLinkState::Gone,
+    };
+
+    if link_target
+        .file_name()
+        .is_none_or(|name| name != identifier)
+    {
+        TrackedLinkState::Gone
+    } else if link_target.is_dir() {
+        TrackedLinkState::Ok(link_target)
+    } else {
+        TrackedLinkState::Dangling(link_target)
+    }
+}
+
+/// A dangling link fixed after its cache directory was removed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SettledLink {
+    pub link: PathBuf,
+    pub cache_dir: PathBuf,
+    /// Whether an empty cache was recreated behind the link, rather than the
+    /// link being removed.
+    pub recreated: bool,
+}
+
+impl fmt::Display for SettledLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.recreated {
+            write!(
+                f,
+                "Recreated empty cache '{}' for link '{}'",
+                self.cache_dir.display(),
+                self.link.display()
+            )
+        } else {
+            write!(f, "Removed dangling link '{}'", self.link.display())
+        }
+    }
+}
+
+/// Fixes a dangling link after its cache directory is gone.
+///
+/// Links named `target` get an empty cache directory recreated so plain cargo
+/// keeps building through them; any other link is removed.
+fn settle_dangling_link(link: &Path, link_target: &Path) -> io::Result<SettledLink> {
+    let recreated = link
+        .file_name()
+        .is_some_and(|name| name == CARGO_TARGET_NAME);
+    if recreated {
+        fs::create_dir_all(link_target)?;
+    } else {
+        fs::remove_file(link)?;
+    }
+
+    Ok(SettledLink {
+        link: link.to_path_buf(),
+        cache_dir: link_target.to_path_buf(),
+        recreated,
+    })
+}
+
+/// Settles the tracked links of the given projects whose caches were removed,
+/// and stops tracking links that are no longer rcargo's.
+///
+/// Passing `None` settles the links of every registered project. Returns the
+/// dangling links that were fixed.
+pub fn settle_project_links(
+    identifiers: Option<&[String]>,
+) -> Result<Vec<SettledLink>, Box<dyn std::error::Error>> {
+    Registry::update(|registry| {
+        let mut settled = Vec::new();
+        for (identifier, record) in registry.projects.iter_mut() {
+            if identifiers.is_some_and(|identifiers| !identifiers.contains(identifier)) {
+                continue;
+            }
+
+            record
+                .links
+                .retain(|link| match inspect_tracked_link(link, identifier) {
+                    TrackedLinkState::Ok(_) => true,
+                    TrackedLinkState::Dangling(link_target) => {
+                        match settle_dangling_link(link, &link_target) {
+                            Ok(settled_link) => {
+                                let kept = settled_link.recreated;
+                                settled.push(settled_link);
+                                kept
+                            }
+                            Err(e) => {
+                                eprintln!(
+                                    "Warning: Failed to settle dangling link '{}': {}",
+                                    link.display(),
+                                    e
+                                );
+                                true
+                            }
+                        }
+                    }
+                    TrackedLinkState::Gone => false,
+                });
+        }
+        settled
+    })
+}
+
+/// Handles the links command, listing tracked links and optionally pruning
+/// broken ones.
+pub fn handle_links_command(prune: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if prune {
+        let settled = settle_project_links(None)?;
+        if settled.is_empty() {
+            println!("No dangling target links found");
+        }
+        for settled_link in settled {
+            println!("{}", settled_link);
+        }
+        return Ok(());
+    }
+
+    let registry = Registry::load()?;
+    let mut found = false;
+
+    for (identifier, record) in &registry.projects {
+        for link in &record.links {
+            if !found {
+                println!("Tracked target links:");
+                found = true;
+            }
+
+            let state = match inspect_tracked_link(link, identifier) {
+                TrackedLinkState::Ok(link_target) => format!("-> {}", link_target.display()),
+                TrackedLinkState::Dangling(link_target) => {
+                    format!("-> {} (dangling)", link_target.display())
+                }
+                TrackedLinkState::Gone => "(no longer an rcargo link)".to_string(),
+            };
+            println!("  {}: {} {}", identifier, link.display(), state);
+        }
+    }
+
+    if !found {
+        println!("No tracked target links found");
+    }
+    Ok(())
+}
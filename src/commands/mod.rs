@@ -1,5 +1,19 @@
+pub mod adopt;
+pub mod eject;
+pub mod explain;
+pub mod links;
+pub mod pin;
 pub mod purge;
 pub mod size;
+pub mod status;
+pub mod sweep;
 
+pub use adopt::handle_adopt_command;
+pub use eject::handle_eject_command;
+pub use explain::handle_explain_command;
+pub use links::handle_links_command;
+pub use pin::handle_pin_command;
 pub use purge::handle_purge_command;
 pub use size::handle_size_command;
+pub use status::handle_status_command;
+pub use sweep::handle_sweep_command;
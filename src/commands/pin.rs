@@ -0,0 +1,24 @@
+use crate::registry::Registry;
+use crate::utils::{get_cargo_path, resolve_project};
+
+/// Handles the pin and unpin commands for the current project.
+///
+/// Pinned caches are skipped by purges and automatic cleanup unless
+/// `--include-pinned` is given.
+pub fn handle_pin_command(pinned: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let project = resolve_project(&get_cargo_path())?;
+
+    let was_pinned = Registry::update(|registry| {
+        let record = registry.record_mut(&project.identifier, &project.path);
+        std::mem::replace(&mut record.pinned, pinned)
+    })?;
+
+    let name = project.identifier.name();
+    match (was_pinned, pinned) {
+        (false, true) => println!("Pinned project '{}' cache", name),
+        (true, true) => println!("Project '{}' cache is already pinned", name),
+        (true, false) => println!("Unpinned project '{}' cache", name),
+        (false, false) => println!("Project '{}' cache is not pinned", name),
+    }
+    Ok(())
+}
@@ -1,84 +1,980 @@
-use crate::get_target_dir;
-use crate::utils::{ProjectIdentifier, calculate_directory_size, format_size};
-use std::env;
+use super::links::{SettledLink, settle_project_links};
+use crate::PurgeArgs;
+use crate::layout::{ArtifactCategory, TargetLayout};
+use crate::lock::{CacheBusy, PurgeLock, lock_for_purge};
+use crate::registry::{Registry, unix_now};
+use crate::report::{OutputFormat, SCHEMA_VERSION, print_csv, serde_plain};
+use crate::roots::{CacheDir, list_caches, project_caches, target_roots};
+use crate::trash::{PurgeTrash, RestoreOutcome, RestoredPurge, restore_latest_purge};
+use crate::utils::{
+    ProjectIdentifier, calculate_directory_size, format_age, format_size, get_cargo_path,
+    glob_match, resolve_project,
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
 use std::fs;
-use std::io::{self, Write};
-use std::path::PathBuf;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-/// Prompts the user for confirmation before purging.
-fn confirm_purge(message: &str) -> Result<bool, Box<dyn std::error::Error>> {
-    print!("{} (y/N): ", message);
+/// Exit code of a purge that needs confirmation while stdin is not a terminal.
+pub const EXIT_CONFIRMATION_REQUIRED: i32 = 3;
+
+/// A purge needs an answer from the user, but cannot ask for it.
+#[derive(Debug)]
+pub struct ConfirmationRequired {
+    /// Why the user cannot be asked.
+    reason: &'static str,
+    /// Whether the answer is the selection of `--interactive`, which `--yes`
+    /// does not replace.
+    selection: bool,
+}
+
+impl ConfirmationRequired {
+    fn new(reason: &'static str) -> Self {
+        ConfirmationRequired {
+            reason,
+            selection: false,
+        }
+    }
+
+    fn selection(reason: &'static str) -> Self {
+        ConfirmationRequired {
+            reason,
+            selection: true,
+        }
+    }
+}
+
+impl fmt::Display for ConfirmationRequired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.selection {
+            write!(
+                f,
+                "Refusing to prompt for a selection: {} (name the caches with patterns, --path or --all instead of --interactive)",
+                self.reason
+            )
+        } else {
+            write!(
+                f,
+                "Refusing to prompt for confirmation: {} (use --yes to purge without confirmation)",
+                self.reason
+            )
+        }
+    }
+}
+
+impl std::error::Error for ConfirmationRequired {}
+
+/// Prints a prompt and reads the answer from the terminal.
+///
+/// Fails instead of reading from a pipe or closed stdin, which would hang or
+/// silently cancel a scripted purge.
+fn prompt(message: &str) -> Result<String, Box<dyn std::error::Error>> {
+    if !io::stdin().is_terminal() {
+        return Err(ConfirmationRequired::new("stdin is not a terminal").into());
+    }
+
+    print!("{}", message);
     io::stdout().flush()?;
 
     let mut input = String::new();
     io::stdin().read_line(&mut input)?;
+    Ok(input.trim().to_string())
+}
 
-    let answer = input.trim().to_lowercase();
+/// Prompts the user for confirmation before purging.
+fn confirm_purge(message: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    let answer = prompt(&format!("{} (y/N): ", message))?.to_lowercase();
     Ok(answer == "y" || answer == "yes")
 }
 
-/// Handles the purge command to clean up cached target directories.
-pub fn handle_purge_command(all: bool, yes: bool) -> Result<(), Box<dyn std::error::Error>> {
-    let target_dir = get_target_dir();
-    let target_path = PathBuf::from(target_dir);
+/// Moves paths of a project cache to the trash unless a running build is using it.
+///
+/// With `wait`, busy caches are waited for instead of being skipped. Paths that
+/// cannot be moved to the trash are deleted directly.
+fn remove_cache(
+    cache: &CacheDir,
+    paths: &[PathBuf],
+    args: &PurgeArgs,
+    trash: &mut PurgeTrash,
+) -> io::Result<Result<(), CacheBusy>> {
+    let _lock = match lock_cache(cache, args)? {
+        Ok(lock) => lock,
+        Err(busy) => return Ok(Err(busy)),
+    };
+    trash_paths(cache, paths, trash)?;
+    Ok(Ok(()))
+}
 
-    if !all {
-        // Clean current project
-        let project_path = env::current_dir()?;
-        let project_identifier = ProjectIdentifier::new(&project_path)?;
+/// Locks a project cache for purging, waiting for it with `wait`.
+fn lock_cache(cache: &CacheDir, args: &PurgeArgs) -> io::Result<Result<PurgeLock, CacheBusy>> {
+    let cache_dir = cache.path.as_path();
+    let mut lock = lock_for_purge(cache_dir, false)?;
 
-        let project_target_dir = target_path.join(project_identifier.identifier());
+    if let Err(busy) = &lock
+        && args.wait
+    {
+        note(
+            args,
+            format_args!(
+                "Waiting for '{}' to be released ({})...",
+                cache_dir.display(),
+                busy
+            ),
+        );
+        lock = lock_for_purge(cache_dir, true)?;
+    }
+    Ok(lock)
+}
 
-        if project_target_dir.exists() {
-            let size_before = calculate_directory_size(&project_target_dir)?;
-            let size_str = format_size(size_before);
+/// Moves paths of a locked project cache to the trash, deleting those that
+/// cannot be moved there.
+fn trash_paths(cache: &CacheDir, paths: &[PathBuf], trash: &mut PurgeTrash) -> io::Result<()> {
+    for path in paths {
+        let metadata = match fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        if trash.add(&cache.root.path, path)? {
+            continue;
+        }
 
-            let should_purge = yes
-                || confirm_purge(&format!(
-                    "Are you sure you want to purge project '{}' cache ({})?",
-                    project_identifier.name(),
-                    size_str
-                ))?;
+        if metadata.is_dir() {
+            fs::remove_dir_all(path)?;
+        } else {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
 
-            if should_purge {
-                fs::remove_dir_all(&project_target_dir)?;
-                println!(
-                    "Purged current project '{}' cache (freed {})",
-                    project_identifier.name(),
-                    size_str
+/// Stores the settled target links with the purge, so an undo can put them
+/// back; failing only costs the links.
+fn record_settled_links(trash: &mut PurgeTrash, settled_links: &[SettledLink]) {
+    if let Err(e) = trash.record_links(settled_links) {
+        eprintln!(
+            "Warning: Could not record the target links for `rcargo purge --undo`: {}",
+            e
+        );
+    }
+}
+
+/// Starts the background deletion of purged paths and tells how to undo it.
+///
+/// Returns how long the purge can be undone, if at all.
+fn finish_trash(trash: PurgeTrash, args: &PurgeArgs) -> Option<Duration> {
+    match trash.finish() {
+        Ok(Some(grace)) if !grace.is_zero() => {
+            note(
+                args,
+                format_args!(
+                    "Purged files are deleted in the background; run `rcargo purge --undo` within {} minutes to restore them",
+                    grace.as_secs() / 60
+                ),
+            );
+            Some(grace)
+        }
+        Ok(Some(_)) => {
+            note(
+                args,
+                "Space is low, deleting purged files in the background",
+            );
+            None
+        }
+        Ok(None) => None,
+        Err(e) => {
+            eprintln!(
+                "Warning: Failed to start deleting purged files in the background: {}",
+                e
+            );
+            None
+        }
+    }
+}
+
+/// Prints a progress message, to stderr when stdout carries JSON or CSV.
+fn note(args: &PurgeArgs, message: impl fmt::Display) {
+    if args.format.is_human() {
+        println!("{}", message);
+    } else {
+        eprintln!("{}", message);
+    }
+}
+
+/// What happened to a cache selected for purging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum PurgeStatus {
+    Purged,
+    WouldPurge,
+    Skipped,
+    Failed,
+}
+
+/// Why a cache was skipped or failed to purge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum PurgeReason {
+    Pinned,
+    InUse,
+    Error,
+}
+
+/// One cache in machine-readable purge output.
+#[derive(Serialize)]
+struct PurgeResult {
+    identifier: String,
+    /// Name of the target root holding the cache.
+    root: String,
+    status: PurgeStatus,
+    reason: Option<PurgeReason>,
+    /// Human-readable details of the reason.
+    detail: Option<String>,
+    bytes: u64,
+    paths: Vec<PathBuf>,
+}
+
+/// Machine-readable purge output.
+#[derive(Serialize)]
+struct PurgeReport {
+    schema_version: u32,
+    dry_run: bool,
+    only: Vec<String>,
+    results: Vec<PurgeResult>,
+    /// Bytes freed, or that would be freed by a dry run.
+    freed_bytes: u64,
+    /// How long `rcargo purge --undo` can restore the purge.
+    undo_window_seconds: Option<u64>,
+}
+
+impl PurgeReport {
+    fn push(
+        &mut self,
+        target: &PurgeTarget,
+        status: PurgeStatus,
+        reason: Option<PurgeReason>,
+        detail: Option<String>,
+    ) {
+        self.results.push(PurgeResult {
+            identifier: target.cache.identifier.clone(),
+            root: target.cache.root.name.clone(),
+            status,
+            reason,
+            detail,
+            bytes: target.size,
+            paths: target.paths.clone(),
+        });
+    }
+
+    /// Prints the report as JSON or CSV.
+    fn print(&self, format: OutputFormat) -> Result<(), Box<dyn std::error::Error>> {
+        match format {
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(self)?),
+            OutputFormat::Csv => {
+                let rows: Vec<Vec<String>> = self
+                    .results
+                    .iter()
+                    .map(|result| {
+                        vec![
+                            result.identifier.clone(),
+                            result.root.clone(),
+                            serde_plain(&result.status),
+                            result.reason.as_ref().map(serde_plain).unwrap_or_default(),
+                            result.detail.clone().unwrap_or_default(),
+                            result.bytes.to_string(),
+                        ]
+                    })
+                    .collect();
+                print_csv(
+                    &["identifier", "root", "status", "reason", "detail", "bytes"],
+                    &rows,
                 );
+            }
+            OutputFormat::Table => {}
+        }
+        Ok(())
+    }
+}
+
+/// A project cache selected for purging.
+struct PurgeTarget {
+    cache: CacheDir,
+    /// Paths to delete: the whole cache, or the `--only` artifact categories.
+    paths: Vec<PathBuf>,
+    size: u64,
+}
+
+impl PurgeTarget {
+    /// Selects what to delete from a cache, honoring `--only` categories.
+    fn new(cache: CacheDir, only: &[ArtifactCategory]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut paths = if only.is_empty() {
+            vec![cache.path.clone()]
+        } else {
+            let layout = TargetLayout::scan(&cache.path)?;
+            only.iter()
+                .flat_map(|category| category.paths(&layout))
+                .collect()
+        };
+
+        // Drop duplicates and paths inside other selected paths
+        paths.sort();
+        paths.dedup();
+        let mut selected: Vec<PathBuf> = Vec::new();
+        for path in paths {
+            if !selected.iter().any(|parent| path.starts_with(parent)) {
+                selected.push(path);
+            }
+        }
+
+        let mut size = 0;
+        for path in &selected {
+            size += if path.is_dir() {
+                calculate_directory_size(path)?
             } else {
+                fs::metadata(path)?.len()
+            };
+        }
+
+        Ok(PurgeTarget {
+            cache,
+            paths: selected,
+            size,
+        })
+    }
+
+    /// Whether the whole cache directory is deleted.
+    fn is_whole_cache(&self) -> bool {
+        self.paths == [self.cache.path.clone()]
+    }
+}
+
+/// Handles the purge command to clean up cached target directories.
+pub fn handle_purge_command(args: &PurgeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if args.undo {
+        return undo_purge();
+    }
+
+    // Prompts would end up in the middle of the JSON or CSV output
+    if !args.format.is_human() && !args.yes && !args.dry_run {
+        return Err(ConfirmationRequired::new("the output format is not a table").into());
+    }
+
+    let has_selection =
+        args.all || args.interactive || !args.patterns.is_empty() || !args.paths.is_empty();
+    if !has_selection {
+        return purge_current_project(args);
+    }
+
+    let targets = if args.interactive {
+        match select_interactively(args)? {
+            Some(targets) => targets,
+            None => {
                 println!("Purge cancelled.");
+                return Ok(());
+            }
+        }
+    } else {
+        select_targets(args)?
+    };
+    purge_targets(targets, args)
+}
+
+/// Restores the most recent purge whose files are still in the trash.
+fn undo_purge() -> Result<(), Box<dyn std::error::Error>> {
+    let target_roots: Vec<&Path> = target_roots()?
+        .iter()
+        .map(|root| root.path.as_path())
+        .collect();
+    let Some(RestoredPurge {
+        items: restored,
+        relinked,
+    }) = restore_latest_purge(&target_roots)?
+    else {
+        println!("No purge to undo: purged files have already been deleted");
+        return Ok(());
+    };
+
+    println!("Restoring the most recent purge:");
+    let mut failed = 0;
+    for item in &restored {
+        match &item.outcome {
+            RestoreOutcome::Restored => println!("  Restored '{}'", item.original.display()),
+            RestoreOutcome::Occupied => {
+                println!(
+                    "  Not restored '{}': the path is in use again",
+                    item.original.display()
+                );
+                failed += 1;
+            }
+            RestoreOutcome::Failed(e) => {
+                eprintln!("  Failed to restore '{}': {}", item.original.display(), e);
+                failed += 1;
+            }
+        }
+    }
+
+    for link in &relinked {
+        println!("  Recreated link '{}'", link.display());
+    }
+
+    if failed > 0 {
+        return Err(format!("Failed to restore {} purged paths", failed).into());
+    }
+    Ok(())
+}
+
+/// Purges the cache of the project containing the current directory, in every
+/// target root holding one.
+fn purge_current_project(args: &PurgeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let project_identifier = resolve_project(&get_cargo_path())?.identifier;
+
+    let caches = project_caches(&project_identifier)?;
+
+    if !args.format.is_human() || !args.only.is_empty() {
+        let mut targets = Vec::new();
+        for cache in caches {
+            targets.push(PurgeTarget::new(cache, &args.only)?);
+        }
+        return purge_targets(targets, args);
+    }
+
+    if caches.is_empty() {
+        println!(
+            "Current project '{}' has no cached target directory to purge",
+            project_identifier.name()
+        );
+        return Ok(());
+    }
+
+    if !args.include_pinned && Registry::load()?.is_pinned(&project_identifier.identifier()) {
+        println!(
+            "Current project '{}' cache is pinned (use --include-pinned to purge it)",
+            project_identifier.name()
+        );
+        return Ok(());
+    }
+
+    let mut size_before = 0;
+    for cache in &caches {
+        size_before += calculate_directory_size(&cache.path)?;
+    }
+    let size_str = format_size(size_before);
+    let cache_paths = caches
+        .iter()
+        .map(|cache| format!("'{}'", cache.path.display()))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    if args.dry_run {
+        println!(
+            "Would purge current project '{}' cache at {} (would free {})",
+            project_identifier.name(),
+            cache_paths,
+            size_str
+        );
+        return Ok(());
+    }
+
+    let should_purge = args.yes
+        || confirm_purge(&format!(
+            "Are you sure you want to purge project '{}' cache ({})?",
+            project_identifier.name(),
+            size_str
+        ))?;
+
+    if !should_purge {
+        println!("Purge cancelled.");
+        return Ok(());
+    }
+
+    // Every cache is locked first, so a busy one leaves the others untouched
+    let mut locks = Vec::new();
+    for cache in &caches {
+        match lock_cache(cache, args)? {
+            Ok(lock) => locks.push(lock),
+            Err(busy) => {
+                return Err(format!(
+                    "Current project '{}' cache is in use: {} (use --wait to wait for it)",
+                    project_identifier.name(),
+                    busy
+                )
+                .into());
+            }
+        }
+    }
+
+    let mut trash = PurgeTrash::new();
+    let trashed = caches
+        .iter()
+        .try_for_each(|cache| trash_paths(cache, std::slice::from_ref(&cache.path), &mut trash));
+    drop(locks);
+    if trashed.is_ok() {
+        println!(
+            "Purged current project '{}' cache (freed {})",
+            project_identifier.name(),
+            size_str
+        );
+        let settled_links = settle_project_links(Some(&[project_identifier.identifier()]))?;
+        for settled_link in &settled_links {
+            println!("{}", settled_link);
+        }
+        record_settled_links(&mut trash, &settled_links);
+    }
+    finish_trash(trash, args);
+    trashed?;
+
+    Ok(())
+}
+
+/// Selects the caches named by `--all`, patterns and `--path` arguments.
+///
+/// A pattern matches a cache by exact identifier, or as a glob against either
+/// the identifier or the project name.
+fn select_targets(args: &PurgeArgs) -> Result<Vec<PurgeTarget>, Box<dyn std::error::Error>> {
+    let mut selected = BTreeMap::new();
+    let mut matched_patterns = vec![false; args.patterns.len()];
+
+    for cache in list_caches()? {
+        let name = ProjectIdentifier::from_identifier(&cache.identifier)
+            .map(|parsed| parsed.name().to_string())
+            .unwrap_or_else(|| cache.identifier.clone());
+
+        let mut is_selected = args.all;
+        for (pattern, matched) in args.patterns.iter().zip(matched_patterns.iter_mut()) {
+            if glob_match(pattern, &cache.identifier) || glob_match(pattern, &name) {
+                *matched = true;
+                is_selected = true;
+            }
+        }
+
+        if is_selected {
+            selected.insert(cache.path.clone(), cache);
+        }
+    }
+
+    for (pattern, matched) in args.patterns.iter().zip(&matched_patterns) {
+        if !matched {
+            note(
+                args,
+                format_args!("No cached target directory matches '{}'", pattern),
+            );
+        }
+    }
+
+    for path in &args.paths {
+        let project_path = fs::canonicalize(path)
+            .map_err(|e| format!("Invalid project path '{}': {}", path.display(), e))?;
+        let caches = project_caches(&ProjectIdentifier::new(&project_path)?)?;
+
+        if !caches.is_empty() {
+            for cache in caches {
+                selected.insert(cache.path.clone(), cache);
             }
         } else {
+            note(
+                args,
+                format_args!(
+                    "Project at '{}' has no cached target directory",
+                    project_path.display()
+                ),
+            );
+        }
+    }
+
+    let mut targets = Vec::new();
+    for cache in selected.into_values() {
+        targets.push(PurgeTarget::new(cache, &args.only)?);
+    }
+    Ok(targets)
+}
+
+/// Lets the user pick caches to purge from a list with their sizes and the
+/// time of their last redirected build.
+///
+/// Returns `None` if nothing was picked.
+fn select_interactively(
+    args: &PurgeArgs,
+) -> Result<Option<Vec<PurgeTarget>>, Box<dyn std::error::Error>> {
+    if !io::stdin().is_terminal() {
+        return Err(ConfirmationRequired::selection("stdin is not a terminal").into());
+    }
+
+    let mut caches = list_caches()?;
+    if caches.is_empty() {
+        return Ok(Some(Vec::new()));
+    }
+    caches.sort_by_key(|cache| cache.to_string());
+
+    let registry = Registry::load()?;
+    let now = unix_now();
+    let labels: Vec<String> = caches.iter().map(CacheDir::to_string).collect();
+    let width = labels
+        .iter()
+        .map(|label| label.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    println!("Cached target directories:");
+    for (index, (label, cache)) in labels.iter().zip(&caches).enumerate() {
+        let identifier = &cache.identifier;
+        let last_used = registry
+            .projects
+            .get(identifier)
+            .and_then(|record| record.last_used)
+            .map(|last_used| format!("last used {}", format_age(now.saturating_sub(last_used))))
+            .unwrap_or_else(|| "never used by rcargo".to_string());
+        let pinned = if registry.is_pinned(identifier) {
+            ", pinned"
+        } else {
+            ""
+        };
+        println!(
+            "  {:>3}) {:<width$}  {:>10}  {}{}",
+            index + 1,
+            label,
+            format_size(calculate_directory_size(&cache.path)?),
+            last_used,
+            pinned,
+            width = width
+        );
+    }
+
+    let answer = prompt("Select caches to purge (e.g. 1 3 5-7, or all; empty to cancel): ")?;
+    let selection = parse_selection(&answer, caches.len())?;
+    if selection.is_empty() {
+        return Ok(None);
+    }
+
+    let mut targets = Vec::new();
+    for index in selection {
+        targets.push(PurgeTarget::new(caches[index].clone(), &args.only)?);
+    }
+    Ok(Some(targets))
+}
+
+/// Parses a selection such as `1 3 5-7` or `all` into zero-based indices of a
+/// list with `count` entries.
+fn parse_selection(input: &str, count: usize) -> Result<BTreeSet<usize>, String> {
+    let mut selection = BTreeSet::new();
+
+    for token in input.split(|c: char| c.is_whitespace() || c == ',') {
+        if token.is_empty() {
+            continue;
+        }
+        if token == "all" {
+            selection.extend(0..count);
+            continue;
+        }
+
+        let parse = |number: &str| {
+            number
+                .parse::<usize>()
+                .ok()
+                .filter(|number| (1..=count).contains(number))
+                .ok_or_else(|| format!("Invalid selection '{}': expected 1 to {}", token, count))
+        };
+        let (start, end) = match token.split_once('-') {
+            Some((start, end)) => (parse(start)?, parse(end)?),
+            None => (parse(token)?, parse(token)?),
+        };
+        if start > end {
+            return Err(format!(
+                "Invalid selection '{}': ranges go from the lower number to the higher",
+                token
+            ));
+        }
+        selection.extend(start - 1..end);
+    }
+
+    Ok(selection)
+}
+
+/// Purges the selected project caches.
+///
+/// Only the project directories are deleted: the target root itself may be a
+/// mount point with its own ownership and permissions, and hidden bookkeeping
+/// files are kept. Caches in use by a running build are skipped, and failures
+/// are reported so one unreadable cache does not stop the rest from being purged.
+fn purge_targets(
+    mut targets: Vec<PurgeTarget>,
+    args: &PurgeArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let human = args.format.is_human();
+    let mut report = PurgeReport {
+        schema_version: SCHEMA_VERSION,
+        dry_run: args.dry_run,
+        only: args.only.iter().map(ArtifactCategory::to_string).collect(),
+        results: Vec::new(),
+        freed_bytes: 0,
+        undo_window_seconds: None,
+    };
+
+    if !args.only.is_empty() {
+        targets.retain(|target| !target.paths.is_empty());
+    }
+
+    // Pinned caches survive routine cleanup
+    let mut pinned_count = 0;
+    if !args.include_pinned {
+        let registry = Registry::load()?;
+        let (pinned, unpinned): (Vec<_>, Vec<_>) = targets
+            .into_iter()
+            .partition(|target| registry.is_pinned(&target.cache.identifier));
+        for target in &pinned {
+            if human {
+                println!(
+                    "  Skipped '{}': pinned (use --include-pinned to purge it)",
+                    target.cache
+                );
+            }
+            report.push(
+                target,
+                PurgeStatus::Skipped,
+                Some(PurgeReason::Pinned),
+                None,
+            );
+        }
+        pinned_count = pinned.len();
+        targets = unpinned;
+    }
+
+    if targets.is_empty() {
+        if !human {
+            return report.print(args.format);
+        }
+        if pinned_count > 0 {
             println!(
-                "Current project '{}' has no cached target directory to purge",
-                project_identifier.name()
+                "{} pinned cache{} skipped, nothing else to purge",
+                pinned_count,
+                if pinned_count == 1 { "" } else { "s" }
             );
+        } else if args.only.is_empty() {
+            println!("No cached target directories found to purge");
+        } else {
+            println!("No {} artifacts found to purge", describe_only(&args.only));
         }
+        return Ok(());
+    }
+
+    let total_size: u64 = targets.iter().map(|target| target.size).sum();
+
+    if args.dry_run {
+        if human {
+            println!("Would purge:");
+        }
+        for target in &targets {
+            match lock_for_purge(&target.cache.path, false)? {
+                Ok(_) => {
+                    report.freed_bytes += target.size;
+                    report.push(target, PurgeStatus::WouldPurge, None, None);
+                    if !human {
+                        continue;
+                    }
+                    println!("  {} ({})", target.cache, format_size(target.size));
+                    if !target.is_whole_cache() {
+                        for path in &target.paths {
+                            let relative = path.strip_prefix(&target.cache.path).unwrap_or(path);
+                            println!("    {}", relative.display());
+                        }
+                    }
+                }
+                Err(busy) => {
+                    if human {
+                        println!(
+                            "  {} ({}, would be skipped: {})",
+                            target.cache,
+                            format_size(target.size),
+                            busy
+                        );
+                    }
+                    report.push(
+                        target,
+                        PurgeStatus::Skipped,
+                        Some(PurgeReason::InUse),
+                        Some(busy.to_string()),
+                    );
+                }
+            }
+        }
+        if !human {
+            return report.print(args.format);
+        }
+        println!("Would free {}", format_size(report.freed_bytes));
+        return Ok(());
+    }
+
+    let prompt = if !args.only.is_empty() {
+        format!(
+            "Are you sure you want to purge {} artifacts from {} cached target directories ({})?",
+            describe_only(&args.only),
+            targets.len(),
+            format_size(total_size)
+        )
+    } else if args.all {
+        format!(
+            "Are you sure you want to purge ALL cached target directories ({})?",
+            format_size(total_size)
+        )
     } else {
-        // Clean all projects
-        if target_path.exists() {
-            let total_size_before = calculate_directory_size(&target_path)?;
-            let size_str = format_size(total_size_before);
-
-            let should_purge = yes
-                || confirm_purge(&format!(
-                    "Are you sure you want to purge ALL cached target directories ({})?",
-                    size_str
-                ))?;
-
-            if should_purge {
-                fs::remove_dir_all(&target_path)?;
-                fs::create_dir_all(&target_path)?;
-                println!("Purged all cached target directories (freed {})", size_str);
-            } else {
-                println!("Purge cancelled.");
+        format!(
+            "Are you sure you want to purge {} cached target directories ({})?",
+            targets.len(),
+            format_size(total_size)
+        )
+    };
+    // Picking the caches from the list already was the confirmation
+    let should_purge = args.yes || args.interactive || confirm_purge(&prompt)?;
+
+    if !should_purge {
+        println!("Purge cancelled.");
+        return Ok(());
+    }
+
+    let mut trash = PurgeTrash::new();
+    let mut purged = Vec::new();
+    let mut skipped = 0;
+    let mut failed = 0;
+
+    for target in targets {
+        match remove_cache(&target.cache, &target.paths, args, &mut trash) {
+            Ok(Ok(())) => {
+                if human {
+                    println!(
+                        "  Purged '{}' (freed {})",
+                        target.cache,
+                        format_size(target.size)
+                    );
+                }
+                report.freed_bytes += target.size;
+                report.push(&target, PurgeStatus::Purged, None, None);
+                if target.is_whole_cache() {
+                    purged.push(target.cache.identifier.clone());
+                }
             }
+            Ok(Err(busy)) => {
+                if human {
+                    println!("  Skipped '{}': {}", target.cache, busy);
+                }
+                report.push(
+                    &target,
+                    PurgeStatus::Skipped,
+                    Some(PurgeReason::InUse),
+                    Some(busy.to_string()),
+                );
+                skipped += 1;
+            }
+            Err(e) => {
+                eprintln!("  Failed to purge '{}': {}", target.cache, e);
+                report.push(
+                    &target,
+                    PurgeStatus::Failed,
+                    Some(PurgeReason::Error),
+                    Some(e.to_string()),
+                );
+                failed += 1;
+            }
+        }
+    }
+
+    let settled_links = settle_project_links(Some(&purged))?;
+    for settled_link in &settled_links {
+        note(args, settled_link);
+    }
+    record_settled_links(&mut trash, &settled_links);
+
+    if human {
+        if args.only.is_empty() {
+            println!(
+                "Purged {} cached target directories (freed {})",
+                purged.len(),
+                format_size(report.freed_bytes)
+            );
         } else {
-            println!("No cached target directories found to purge");
+            println!(
+                "Purged {} artifacts (freed {})",
+                describe_only(&args.only),
+                format_size(report.freed_bytes)
+            );
         }
+        if skipped > 0 {
+            println!(
+                "Skipped {} cached target directories in use by running builds (use --wait to wait for them)",
+                skipped
+            );
+        }
+    }
+    report.undo_window_seconds = finish_trash(trash, args).map(|grace| grace.as_secs());
+    if !human {
+        report.print(args.format)?;
+    }
+    if failed > 0 {
+        return Err(format!("Failed to purge {} cached target directories", failed).into());
     }
 
     Ok(())
 }
+
+/// Describes the `--only` categories for messages, e.g. `incremental, doc`.
+fn describe_only(only: &[ArtifactCategory]) -> String {
+    only.iter()
+        .map(ArtifactCategory::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selected(input: &str, count: usize) -> Vec<usize> {
+        parse_selection(input, count).unwrap().into_iter().collect()
+    }
+
+    #[test]
+    fn selection_reads_single_numbers() {
+        assert_eq!(selected("1", 3), vec![0]);
+        assert_eq!(selected("3 1", 3), vec![0, 2]);
+        assert_eq!(selected("1,3", 3), vec![0, 2]);
+        assert_eq!(selected(" 2 , 3 ", 3), vec![1, 2]);
+    }
+
+    #[test]
+    fn selection_reads_ranges() {
+        assert_eq!(selected("2-4", 5), vec![1, 2, 3]);
+        assert_eq!(selected("1-1", 5), vec![0]);
+        assert_eq!(selected("1-2 5", 5), vec![0, 1, 4]);
+        assert_eq!(selected("all", 3), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn selection_rejects_reversed_ranges() {
+        let error = parse_selection("4-2", 5).unwrap_err();
+        assert!(error.contains("'4-2'"), "{error}");
+        assert!(error.contains("lower number to the higher"), "{error}");
+    }
+
+    #[test]
+    fn selection_rejects_out_of_range_values() {
+        for input in ["0", "4", "2-4", "0-2", "-1", "1-", "x", "1.5"] {
+            let error = parse_selection(input, 3).unwrap_err();
+            assert!(error.contains("expected 1 to 3"), "{input}: {error}");
+        }
+    }
+
+    #[test]
+    fn selection_merges_duplicates() {
+        assert_eq!(selected("2 2", 3), vec![1]);
+        assert_eq!(selected("1-3 2 3", 3), vec![0, 1, 2]);
+        assert_eq!(selected("all 1", 2), vec![0, 1]);
+    }
+
+    #[test]
+    fn selection_of_nothing_is_empty() {
+        assert!(selected("", 3).is_empty());
+        assert!(selected("  , ", 3).is_empty());
+        assert!(selected("all", 0).is_empty());
+    }
+}
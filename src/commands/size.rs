@@ -1,71 +1,1067 @@
-use crate::get_target_dir;
-use crate::utils::{ProjectIdentifier, calculate_directory_size, format_size, is_rust_project};
-use std::env;
-use std::fs;
+use crate::SizeArgs;
+use crate::layout::TargetLayout;
+use crate::registry::{Registry, unix_now};
+use crate::report::{MachineFormat, OutputFormat, SCHEMA_VERSION, print_csv, serde_plain};
+use crate::roots::{
+    CacheDir, LinkedProfile, linked_profile_dirs, list_caches, project_caches, target_roots,
+};
+use crate::utils::{
+    DirectoryUsage, ProjectIdentifier, UsageMeter, filesystem_space, filesystem_type, format_age,
+    format_size, get_cargo_path, is_ram_backed_filesystem, is_ram_expected, is_rust_project,
+    measure_directory, resolve_project,
+};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use std::{fs, io};
+
+/// One project cache in machine-readable size output.
+#[derive(Serialize)]
+struct CacheRecord {
+    identifier: String,
+    /// Name of the target root holding the cache.
+    root: String,
+    /// Identifier as shown in tables, prefixed with the root when there are several.
+    #[serde(skip)]
+    label: String,
+    name: String,
+    /// Project directory, if rcargo has built the project.
+    source_path: Option<PathBuf>,
+    #[serde(flatten)]
+    usage: DirectoryUsage,
+    /// Unix timestamp of the last redirected build.
+    last_used: Option<u64>,
+    /// Unix timestamp of when the cache directory was created, if the
+    /// filesystem records it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created: Option<u64>,
+    pinned: bool,
+    /// Profile directories kept in other roots, whose size counts there.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    linked_profiles: Vec<LinkedProfile>,
+    /// Parts of the cache, with `--breakdown`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    breakdown: Option<Vec<BreakdownEntry>>,
+}
+
+/// What a part of a cache holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum BreakdownCategory {
+    /// Compiled dependencies and crate outputs (`deps/`).
+    Deps,
+    /// Incremental compilation state.
+    Incremental,
+    /// Build script binaries and outputs (`build/`).
+    Build,
+    /// Cargo's freshness tracking (`.fingerprint/`).
+    Fingerprint,
+    /// Final binaries, libraries and examples next to `deps/`.
+    Artifacts,
+    /// Generated documentation.
+    Doc,
+    /// Anything rcargo does not recognize, such as `.rustc_info.json`.
+    Other,
+}
+
+impl BreakdownCategory {
+    /// Classifies an entry of a profile directory by its name.
+    fn of_profile_entry(name: &str) -> Self {
+        match name {
+            "deps" => BreakdownCategory::Deps,
+            "incremental" => BreakdownCategory::Incremental,
+            "build" => BreakdownCategory::Build,
+            ".fingerprint" => BreakdownCategory::Fingerprint,
+            _ => BreakdownCategory::Artifacts,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            BreakdownCategory::Deps => "deps",
+            BreakdownCategory::Incremental => "incremental",
+            BreakdownCategory::Build => "build",
+            BreakdownCategory::Fingerprint => ".fingerprint",
+            BreakdownCategory::Artifacts => "artifacts",
+            BreakdownCategory::Doc => "doc",
+            BreakdownCategory::Other => "other",
+        }
+    }
+}
+
+/// One part of a cache in `--breakdown` output.
+#[derive(Debug, Serialize)]
+struct BreakdownEntry {
+    /// Target triple for cross builds, `None` for host builds and top-level entries.
+    triple: Option<String>,
+    /// Profile directory, `None` for documentation and unrecognized entries.
+    profile: Option<String>,
+    category: BreakdownCategory,
+    #[serde(flatten)]
+    usage: DirectoryUsage,
+}
+
+impl BreakdownEntry {
+    /// Heading of the profile this entry belongs to, or its own label.
+    fn group_label(&self) -> String {
+        let name = self.profile.as_deref().unwrap_or(self.category.label());
+        match &self.triple {
+            Some(triple) => format!("{}/{}", triple, name),
+            None => name.to_string(),
+        }
+    }
+}
+
+/// Adds a measurement to the matching breakdown entry, creating it if needed.
+fn add_to_breakdown(
+    entries: &mut Vec<BreakdownEntry>,
+    triple: Option<&str>,
+    profile: Option<&str>,
+    category: BreakdownCategory,
+    usage: DirectoryUsage,
+) {
+    let existing = entries.iter_mut().find(|entry| {
+        entry.triple.as_deref() == triple
+            && entry.profile.as_deref() == profile
+            && entry.category == category
+    });
+    match existing {
+        Some(entry) => entry.usage.add(usage),
+        None => entries.push(BreakdownEntry {
+            triple: triple.map(str::to_string),
+            profile: profile.map(str::to_string),
+            category,
+            usage,
+        }),
+    }
+}
+
+/// Splits a cache into profiles, target triples and the parts of each.
+///
+/// Profile directories are measured first, so files cargo hard-links from
+/// `deps/` into the profile directory only count as allocated under `deps`.
+fn measure_breakdown(cache_dir: &Path) -> io::Result<Vec<BreakdownEntry>> {
+    let layout = TargetLayout::scan(cache_dir)?;
+    let mut meter = UsageMeter::default();
+    let mut entries = Vec::new();
+
+    for profile in &layout.profiles {
+        let mut parts = Vec::new();
+        for entry in fs::read_dir(&profile.path)? {
+            let entry = entry?;
+            let category =
+                BreakdownCategory::of_profile_entry(&entry.file_name().to_string_lossy());
+            parts.push((category, entry.path()));
+        }
+        parts.sort();
+
+        for (category, path) in parts {
+            add_to_breakdown(
+                &mut entries,
+                profile.triple.as_deref(),
+                Some(&profile.profile),
+                category,
+                meter.measure(&path)?,
+            );
+        }
+    }
+
+    let triple_of = |dir: &Path| {
+        layout
+            .triples
+            .iter()
+            .find(|(_, path)| path == dir)
+            .map(|(triple, _)| triple.as_str())
+    };
+    for doc in &layout.docs {
+        let triple = doc.parent().and_then(triple_of);
+        add_to_breakdown(
+            &mut entries,
+            triple,
+            None,
+            BreakdownCategory::Doc,
+            meter.measure(doc)?,
+        );
+    }
+
+    let is_known = |path: &Path| {
+        layout.docs.iter().any(|doc| doc == path)
+            || layout.profiles.iter().any(|profile| profile.path == path)
+    };
+    for entry in fs::read_dir(cache_dir)? {
+        let path = entry?.path();
+        if is_known(&path) {
+            continue;
+        }
+        match triple_of(&path) {
+            Some(triple) => {
+                for nested in fs::read_dir(&path)? {
+                    let nested = nested?.path();
+                    if !is_known(&nested) {
+                        add_to_breakdown(
+                            &mut entries,
+                            Some(triple),
+                            None,
+                            BreakdownCategory::Other,
+                            meter.measure(&nested)?,
+                        );
+                    }
+                }
+            }
+            None => add_to_breakdown(
+                &mut entries,
+                None,
+                None,
+                BreakdownCategory::Other,
+                meter.measure(&path)?,
+            ),
+        }
+    }
+
+    // Profiles first, then documentation and unrecognized entries
+    entries.sort_by(|a, b| {
+        (a.profile.is_none(), &a.triple, &a.profile, a.category).cmp(&(
+            b.profile.is_none(),
+            &b.triple,
+            &b.profile,
+            b.category,
+        ))
+    });
+    Ok(entries)
+}
+
+/// Prints a breakdown as an indented tree with each part's share of the cache.
+fn print_breakdown(entries: &[BreakdownEntry], total_size: u64, indent: usize) {
+    let share_of = |size: u64| {
+        if total_size > 0 {
+            size as f64 / total_size as f64
+        } else {
+            0.0
+        }
+    };
+    let print_row = |label: &str, depth: usize, size: u64| {
+        let share = share_of(size);
+        println!(
+            "{:indent$}{:<width$}  {:>10}  {:>5.1}%  {}",
+            "",
+            label,
+            format_size(size),
+            share * 100.0,
+            share_bar(share),
+            indent = indent + depth * 2,
+            width = 40 - depth * 2
+        );
+    };
+
+    let mut start = 0;
+    while start < entries.len() {
+        let label = entries[start].group_label();
+        let end = start
+            + entries[start..]
+                .iter()
+                .take_while(|entry| entry.group_label() == label)
+                .count();
+        let group = &entries[start..end];
+
+        if group[0].profile.is_some() {
+            let group_size = group.iter().map(|entry| entry.usage.allocated_bytes).sum();
+            print_row(&label, 0, group_size);
+            for entry in group {
+                print_row(entry.category.label(), 1, entry.usage.allocated_bytes);
+            }
+        } else {
+            for entry in group {
+                print_row(&label, 0, entry.usage.allocated_bytes);
+            }
+        }
+        start = end;
+    }
+}
+
+/// Machine-readable size output.
+#[derive(Serialize)]
+struct SizeReport {
+    schema_version: u32,
+    /// Preferred target root.
+    target_root: PathBuf,
+    projects: Vec<CacheRecord>,
+    /// Space used by every cache, as in the table's total.
+    total: DirectoryUsage,
+    /// Space used by the listed caches, after `--min-size` and `--top`.
+    listed_total: DirectoryUsage,
+    /// Filesystem holding the preferred target root, if it could be queried.
+    filesystem: Option<FilesystemReport>,
+    roots: Vec<RootReport>,
+}
+
+/// Usage of one target root in machine-readable size output.
+#[derive(Serialize)]
+struct RootReport {
+    name: String,
+    path: PathBuf,
+    /// Space used by every cache in this root.
+    total: DirectoryUsage,
+    filesystem: Option<FilesystemReport>,
+}
+
+/// Capacity of the filesystem holding the target root.
+#[derive(Serialize)]
+struct FilesystemReport {
+    /// Filesystem type such as `tmpfs` or `ext4`, if known.
+    fs_type: Option<String>,
+    ram_backed: bool,
+    total_bytes: u64,
+    available_bytes: u64,
+    total_inodes: u64,
+    available_inodes: u64,
+}
+
+impl FilesystemReport {
+    /// Queries the filesystem holding a path with `statvfs`.
+    fn query(path: &Path) -> Option<Self> {
+        let space = filesystem_space(path).ok()?;
+        let fs_type = filesystem_type(path).ok();
+
+        Some(FilesystemReport {
+            ram_backed: fs_type.as_deref().is_some_and(is_ram_backed_filesystem),
+            fs_type,
+            total_bytes: space.total,
+            available_bytes: space.available,
+            total_inodes: space.total_inodes,
+            available_inodes: space.available_inodes,
+        })
+    }
+
+    /// Warns when `RCARGO_EXPECT_RAM` is set but the filesystem keeps its
+    /// contents on disk.
+    fn warn_if_not_ram(&self, target_path: &Path) {
+        if let Some(fs_type) = &self.fs_type
+            && !self.ram_backed
+            && is_ram_expected()
+        {
+            eprintln!(
+                "Warning: RCARGO_EXPECT_RAM is set but '{}' is on {}, not a RAM-backed filesystem",
+                target_path.display(),
+                fs_type
+            );
+        }
+    }
+
+    /// Prints the capacity of the filesystem and the share used by caches.
+    fn print(&self, used: &DirectoryUsage) {
+        let share = |part: u64, whole: u64| {
+            if whole > 0 {
+                part as f64 / whole as f64 * 100.0
+            } else {
+                0.0
+            }
+        };
+        let inodes_used = used.file_count + used.directory_count;
+
+        println!(
+            "Filesystem: {}{}",
+            self.fs_type.as_deref().unwrap_or("unknown"),
+            if self.ram_backed { " (RAM-backed)" } else { "" }
+        );
+        println!(
+            "  Space:   {} used by caches ({:.1}%), {} free of {}",
+            format_size(used.allocated_bytes),
+            share(used.allocated_bytes, self.total_bytes),
+            format_size(self.available_bytes),
+            format_size(self.total_bytes)
+        );
+        // Filesystems like btrfs report no inode limit
+        if self.total_inodes > 0 {
+            println!(
+                "  Inodes:  {} used by caches ({:.1}%), {} free of {}",
+                inodes_used,
+                share(inodes_used, self.total_inodes),
+                self.available_inodes,
+                self.total_inodes
+            );
+        }
+    }
+}
 
 /// Handles the size command to display target directory sizes.
-pub fn handle_size_command(all: bool) -> Result<(), Box<dyn std::error::Error>> {
-    let target_dir = get_target_dir();
-    let target_path = PathBuf::from(target_dir);
+pub fn handle_size_command(args: &SizeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    // `RCARGO_EXPECT_RAM` is about the preferred root, the others may be disks
+    let primary_root = &target_roots()?[0].path;
+    if let Some(filesystem) = FilesystemReport::query(primary_root) {
+        filesystem.warn_if_not_ram(primary_root);
+    }
+    if args.crates {
+        return show_crate_sizes(args);
+    }
+    if let Some(format) = args.format.machine() {
+        return print_size_report(args, format);
+    }
 
-    if all {
+    if args.all {
         // Show all project sizes
-        show_all_project_sizes(&target_path)?;
+        show_all_project_sizes(args)?;
     } else {
         // Show current project size or all if not in a Rust project
-        let project_path = env::current_dir()?;
+        let project = resolve_project(&get_cargo_path())?;
 
         // Check if current directory is a Rust project
-        if is_rust_project(&project_path) {
+        if is_rust_project(&project.path) {
             // Show current project size
-            let project_identifier = ProjectIdentifier::new(&project_path)?;
-            let project_target_dir = target_path.join(project_identifier.identifier());
+            let project_identifier = project.identifier;
+            let caches = project_caches(&project_identifier)?;
 
-            if project_target_dir.exists() {
-                let size = calculate_directory_size(&project_target_dir)?;
+            if caches.is_empty() {
+                println!(
+                    "Current project '{}' has no cached target directory",
+                    project_identifier.name()
+                );
+            } else if args.breakdown {
+                let breakdowns = caches
+                    .iter()
+                    .map(|cache| measure_breakdown(&cache.path))
+                    .collect::<io::Result<Vec<_>>>()?;
+                let size = breakdowns
+                    .iter()
+                    .flatten()
+                    .map(|entry| entry.usage.allocated_bytes)
+                    .sum();
                 println!(
                     "Current project '{}' target size: {}",
                     project_identifier.name(),
                     format_size(size)
                 );
+                for (cache, breakdown) in caches.iter().zip(&breakdowns) {
+                    if caches.len() > 1 {
+                        println!("  In root {}:", cache.root);
+                        print_breakdown(breakdown, size, 4);
+                    } else {
+                        print_breakdown(breakdown, size, 2);
+                    }
+                }
+                print_project_filesystems(&caches)?;
             } else {
+                let sizes = caches
+                    .iter()
+                    .map(|cache| Ok(measure_directory(&cache.path)?.allocated_bytes))
+                    .collect::<io::Result<Vec<_>>>()?;
                 println!(
-                    "Current project '{}' has no cached target directory",
-                    project_identifier.name()
+                    "Current project '{}' target size: {}",
+                    project_identifier.name(),
+                    format_size(sizes.iter().sum())
                 );
+                if caches.len() > 1 {
+                    for (cache, size) in caches.iter().zip(sizes) {
+                        println!("  In root {}: {}", cache.root, format_size(size));
+                    }
+                }
+                print_project_filesystems(&caches)?;
             }
         } else {
             // Not in a Rust project, show all cached projects
-            show_all_project_sizes(&target_path)?;
+            show_all_project_sizes(args)?;
         }
     }
 
     Ok(())
 }
 
+/// Prints the filesystem holding each of a project's caches and the share
+/// the cache takes up of it.
+fn print_project_filesystems(caches: &[CacheDir]) -> io::Result<()> {
+    for cache in caches {
+        let Some(filesystem) = FilesystemReport::query(&cache.root.path) else {
+            continue;
+        };
+        if caches.len() > 1 {
+            println!("Root {}:", cache.root);
+        }
+        filesystem.print(&measure_directory(&cache.path)?);
+    }
+    Ok(())
+}
+
+/// Order of `rcargo size` listings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SizeSort {
+    /// Largest first
+    #[default]
+    Size,
+    /// Alphabetically by identifier
+    Name,
+    /// Most recently built first
+    LastUsed,
+    /// Oldest cache first, by creation time
+    Age,
+}
+
+/// Width of the bar chart in table output, in characters.
+const BAR_WIDTH: usize = 20;
+
+/// Measures caches and looks up what the registry knows about them.
+fn collect_records(
+    caches: Vec<CacheDir>,
+    registry: &Registry,
+    breakdown: bool,
+) -> Result<Vec<CacheRecord>, Box<dyn std::error::Error>> {
+    let mut records = Vec::new();
+
+    for cache in caches {
+        let label = cache.to_string();
+        let CacheDir {
+            root,
+            identifier,
+            path: cache_dir,
+        } = cache;
+        let record = registry.projects.get(&identifier);
+        let name = match record {
+            Some(record) => record.name.clone(),
+            None => ProjectIdentifier::from_identifier(&identifier)
+                .map(|parsed| parsed.name().to_string())
+                .unwrap_or_else(|| identifier.clone()),
+        };
+
+        let (usage, breakdown) = if breakdown {
+            let entries = measure_breakdown(&cache_dir)?;
+            let mut usage = DirectoryUsage::default();
+            for entry in &entries {
+                usage.add(entry.usage);
+            }
+            (usage, Some(entries))
+        } else {
+            (measure_directory(&cache_dir)?, None)
+        };
+
+        records.push(CacheRecord {
+            pinned: registry.is_pinned(&identifier),
+            identifier,
+            root: root.name,
+            label,
+            name,
+            source_path: record.map(|record| record.path.clone()),
+            usage,
+            last_used: record.and_then(|record| record.last_used),
+            created: fs::metadata(&cache_dir)
+                .and_then(|metadata| metadata.created())
+                .ok()
+                .and_then(|created| created.duration_since(UNIX_EPOCH).ok())
+                .map(|created| created.as_secs()),
+            linked_profiles: linked_profile_dirs(&cache_dir)?,
+            breakdown,
+        });
+    }
+
+    Ok(records)
+}
+
+/// Sorts and filters records as requested by `--sort`, `--reverse`,
+/// `--min-size` and `--top`.
+fn select_records(records: &mut Vec<CacheRecord>, args: &SizeArgs) {
+    records.retain(|record| record.usage.allocated_bytes >= args.min_size.unwrap_or(0));
+
+    match args.sort {
+        SizeSort::Size => records.sort_by(|a, b| {
+            b.usage
+                .allocated_bytes
+                .cmp(&a.usage.allocated_bytes)
+                .then_with(|| a.label.cmp(&b.label))
+        }),
+        SizeSort::Name => records.sort_by(|a, b| a.label.cmp(&b.label)),
+        // Caches never built by rcargo sort as the oldest
+        SizeSort::LastUsed => records.sort_by(|a, b| {
+            b.last_used
+                .cmp(&a.last_used)
+                .then_with(|| a.label.cmp(&b.label))
+        }),
+        // Caches of unknown age sort last
+        SizeSort::Age => records.sort_by(|a, b| {
+            a.created
+                .is_none()
+                .cmp(&b.created.is_none())
+                .then_with(|| a.created.cmp(&b.created))
+                .then_with(|| a.label.cmp(&b.label))
+        }),
+    }
+    if args.reverse {
+        records.reverse();
+    }
+
+    if let Some(top) = args.top {
+        records.truncate(top);
+    }
+}
+
+/// Draws a bar for a share of the total between 0 and 1.
+fn share_bar(share: f64) -> String {
+    let filled = ((share * BAR_WIDTH as f64).round() as usize).min(BAR_WIDTH);
+    format!("{}{}", "█".repeat(filled), "░".repeat(BAR_WIDTH - filled))
+}
+
 /// Shows all cached project target directories and their sizes.
-fn show_all_project_sizes(target_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
-    if target_path.exists() {
-        println!("All cached project target directories:");
-        let mut total_size = 0;
+fn show_all_project_sizes(args: &SizeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let roots = target_roots()?;
+    if !roots.iter().any(|root| root.path.exists()) {
+        println!("No cached target directories found");
+        return Ok(());
+    }
+
+    let registry = Registry::load()?;
+    let mut records = collect_records(list_caches()?, &registry, args.breakdown)?;
+    let cache_count = records.len();
+    let mut total = DirectoryUsage::default();
+    let mut root_totals: HashMap<String, DirectoryUsage> = HashMap::new();
+    for record in &records {
+        total.add(record.usage);
+        root_totals
+            .entry(record.root.clone())
+            .or_default()
+            .add(record.usage);
+    }
+    let total_size = total.allocated_bytes;
+    select_records(&mut records, args);
+
+    println!("All cached project target directories:");
+    let now = unix_now();
+    let width = records
+        .iter()
+        .map(|record| record.label.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    for record in &records {
+        let size = record.usage.allocated_bytes;
+        let share = if total_size > 0 {
+            size as f64 / total_size as f64
+        } else {
+            0.0
+        };
+        let last_used = record
+            .last_used
+            .map(|last_used| format_age(now.saturating_sub(last_used)))
+            .unwrap_or_else(|| "never".to_string());
+        let pinned = if record.pinned { " (pinned)" } else { "" };
+        let linked: String = record
+            .linked_profiles
+            .iter()
+            .map(|linked| {
+                let root = target_roots()
+                    .ok()
+                    .and_then(|roots| {
+                        roots
+                            .iter()
+                            .find(|root| linked.target.starts_with(&root.path))
+                    })
+                    .map(|root| root.name.as_str())
+                    .unwrap_or("another root");
+                format!(" ({} in {})", linked.profile_dir.display(), root)
+            })
+            .collect();
+
+        println!(
+            "  {:<width$}  {:>10}  {:>5.1}%  {}  {}{}{}",
+            record.label,
+            format_size(size),
+            share * 100.0,
+            share_bar(share),
+            last_used,
+            pinned,
+            linked,
+            width = width
+        );
+        if let Some(breakdown) = &record.breakdown {
+            print_breakdown(breakdown, size, 4);
+        }
+    }
+
+    if records.len() < cache_count {
+        let shown_size: u64 = records
+            .iter()
+            .map(|record| record.usage.allocated_bytes)
+            .sum();
+        println!(
+            "Showing {} of {} cached projects ({})",
+            records.len(),
+            cache_count,
+            format_size(shown_size)
+        );
+    }
+    println!("Total cache size: {}", format_size(total_size));
+
+    if let [root] = roots {
+        if let Some(filesystem) = FilesystemReport::query(&root.path) {
+            filesystem.print(&total);
+        }
+        return Ok(());
+    }
+    for root in roots.iter().filter(|root| root.path.exists()) {
+        let used = root_totals.get(&root.name).copied().unwrap_or_default();
+        println!("Root {}: {}", root, format_size(used.allocated_bytes));
+        if let Some(filesystem) = FilesystemReport::query(&root.path) {
+            filesystem.print(&used);
+        }
+    }
+    Ok(())
+}
+
+/// Prints the sizes of the selected caches as JSON or CSV.
+fn print_size_report(
+    args: &SizeArgs,
+    format: MachineFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let caches = if args.all {
+        list_caches()?
+    } else {
+        let project = resolve_project(&get_cargo_path())?;
+        if is_rust_project(&project.path) {
+            project_caches(&project.identifier)?
+        } else {
+            list_caches()?
+        }
+    };
+
+    let mut projects = collect_records(caches, &Registry::load()?, args.breakdown)?;
+
+    // Totals cover every cache, like the table's, before the listing is cut
+    let mut total = DirectoryUsage::default();
+    for project in &projects {
+        total.add(project.usage);
+    }
+    let roots = target_roots()?
+        .iter()
+        .map(|root| {
+            let mut total = DirectoryUsage::default();
+            for project in projects.iter().filter(|project| project.root == root.name) {
+                total.add(project.usage);
+            }
+            RootReport {
+                name: root.name.clone(),
+                path: root.path.clone(),
+                total,
+                filesystem: FilesystemReport::query(&root.path),
+            }
+        })
+        .collect();
+
+    select_records(&mut projects, args);
+    let mut listed_total = DirectoryUsage::default();
+    for project in &projects {
+        listed_total.add(project.usage);
+    }
+    let primary_root = &target_roots()?[0].path;
+    let report = SizeReport {
+        schema_version: SCHEMA_VERSION,
+        target_root: primary_root.clone(),
+        projects,
+        total,
+        listed_total,
+        filesystem: FilesystemReport::query(primary_root),
+        roots,
+    };
+
+    match format {
+        MachineFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        MachineFormat::Csv if args.breakdown => {
+            let rows: Vec<Vec<String>> = report
+                .projects
+                .iter()
+                .flat_map(|project| {
+                    project.breakdown.iter().flatten().map(|entry| {
+                        vec![
+                            project.identifier.clone(),
+                            project.root.clone(),
+                            entry.triple.clone().unwrap_or_default(),
+                            entry.profile.clone().unwrap_or_default(),
+                            serde_plain(&entry.category),
+                            entry.usage.apparent_bytes.to_string(),
+                            entry.usage.allocated_bytes.to_string(),
+                            entry.usage.file_count.to_string(),
+                        ]
+                    })
+                })
+                .collect();
+            print_csv(
+                &[
+                    "identifier",
+                    "root",
+                    "triple",
+                    "profile",
+                    "category",
+                    "apparent_bytes",
+                    "allocated_bytes",
+                    "file_count",
+                ],
+                &rows,
+            );
+        }
+        MachineFormat::Csv => {
+            let rows: Vec<Vec<String>> = report
+                .projects
+                .iter()
+                .map(|project| {
+                    vec![
+                        project.identifier.clone(),
+                        project.root.clone(),
+                        project.name.clone(),
+                        project
+                            .source_path
+                            .as_ref()
+                            .map(|path| path.display().to_string())
+                            .unwrap_or_default(),
+                        project.usage.apparent_bytes.to_string(),
+                        project.usage.allocated_bytes.to_string(),
+                        project.usage.file_count.to_string(),
+                        project
+                            .last_used
+                            .map(|last_used| last_used.to_string())
+                            .unwrap_or_default(),
+                        project.pinned.to_string(),
+                    ]
+                })
+                .collect();
+            print_csv(
+                &[
+                    "identifier",
+                    "root",
+                    "name",
+                    "source_path",
+                    "apparent_bytes",
+                    "allocated_bytes",
+                    "file_count",
+                    "last_used",
+                    "pinned",
+                ],
+                &rows,
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Builds of one crate in a single profile from which it is flagged.
+const MANY_BUILDS: usize = 3;
+
+/// Space used by one crate in a cache, in `--crates` output.
+#[derive(Serialize)]
+struct CrateRecord {
+    name: String,
+    /// Distinct builds across all profiles, e.g. one per feature set or version.
+    builds: usize,
+    /// Whether some profile holds at least `MANY_BUILDS` builds of the crate.
+    many_builds: bool,
+    #[serde(flatten)]
+    usage: DirectoryUsage,
+}
+
+/// Machine-readable `--crates` output.
+#[derive(Serialize)]
+struct CratesReport {
+    schema_version: u32,
+    identifier: String,
+    crates: Vec<CrateRecord>,
+    total: DirectoryUsage,
+}
+
+/// Whether a unit is a build of the crate itself rather than of its build
+/// script or documentation.
+fn is_crate_build(kind: &str) -> bool {
+    kind.split(',').any(|kind| {
+        !kind.is_empty()
+            && !kind.starts_with("build-script-")
+            && !kind.starts_with("run-build-script-")
+            && !kind.starts_with("doc-")
+    })
+}
+
+/// Looks up the record of a crate, creating it if needed.
+fn crate_record<'a>(
+    crates: &'a mut BTreeMap<String, CrateRecord>,
+    name: &str,
+) -> &'a mut CrateRecord {
+    crates
+        .entry(name.to_string())
+        .or_insert_with(|| CrateRecord {
+            name: name.to_string(),
+            builds: 0,
+            many_builds: false,
+            usage: DirectoryUsage::default(),
+        })
+}
+
+/// Attributes the `{name}-{hash}` artifacts in `deps/`, `build/`,
+/// `.fingerprint/` and `examples/` of every profile to their crates.
+///
+/// Artifacts are grouped by hash under the name of the unit fingerprinted with
+/// it, since library files carry a `lib` prefix and underscores instead of
+/// the package name.
+fn measure_crates(cache_dir: &Path) -> io::Result<Vec<CrateRecord>> {
+    let layout = TargetLayout::scan(cache_dir)?;
+    let mut meter = UsageMeter::default();
+    let mut crates: BTreeMap<String, CrateRecord> = BTreeMap::new();
+
+    for profile in &layout.profiles {
+        let units = profile.units()?;
+        let names: HashMap<&str, &str> = units
+            .iter()
+            .map(|unit| (unit.hash.as_str(), unit.name.as_str()))
+            .collect();
+
+        let mut builds: HashMap<&str, usize> = HashMap::new();
+        for unit in units.iter().filter(|unit| is_crate_build(&unit.kind)) {
+            *builds.entry(&unit.name).or_default() += 1;
+        }
+        for (name, count) in builds {
+            let record = crate_record(&mut crates, name);
+            record.builds += count;
+            record.many_builds |= count >= MANY_BUILDS;
+        }
+
+        for artifact in profile.unit_artifacts()? {
+            let name = match names.get(artifact.hash.as_str()) {
+                Some(name) => name,
+                None => artifact.name.strip_prefix("lib").unwrap_or(&artifact.name),
+            };
+            let usage = meter.measure(&artifact.path)?;
+            crate_record(&mut crates, name).usage.add(usage);
+        }
+    }
+
+    Ok(crates.into_values().collect())
+}
+
+/// Shows the space used by each crate in the current project's cache.
+fn show_crate_sizes(args: &SizeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    // Crates have no build time or age of their own to sort by
+    if matches!(args.sort, SizeSort::LastUsed | SizeSort::Age) {
+        let sort = args
+            .sort
+            .to_possible_value()
+            .expect("sort values are not skipped");
+        return Err(format!(
+            "--sort {} does not apply to --crates; use size or name",
+            sort.get_name()
+        )
+        .into());
+    }
+
+    let project = resolve_project(&get_cargo_path())?;
+    let mut merged: BTreeMap<String, CrateRecord> = BTreeMap::new();
+    for cache in project_caches(&project.identifier)? {
+        for measured in measure_crates(&cache.path)? {
+            let record = crate_record(&mut merged, &measured.name);
+            record.builds += measured.builds;
+            record.many_builds |= measured.many_builds;
+            record.usage.add(measured.usage);
+        }
+    }
+    let mut crates: Vec<CrateRecord> = merged.into_values().collect();
+
+    let mut total = DirectoryUsage::default();
+    for record in &crates {
+        total.add(record.usage);
+    }
+    let crate_count = crates.len();
+
+    crates.retain(|record| record.usage.apparent_bytes >= args.min_size.unwrap_or(0));
+    match args.sort {
+        SizeSort::Name => crates.sort_by(|a, b| a.name.cmp(&b.name)),
+        _ => crates.sort_by(|a, b| {
+            b.usage
+                .apparent_bytes
+                .cmp(&a.usage.apparent_bytes)
+                .then_with(|| a.name.cmp(&b.name))
+        }),
+    }
+    if args.reverse {
+        crates.reverse();
+    }
+    if let Some(top) = args.top {
+        crates.truncate(top);
+    }
+
+    match args.format {
+        OutputFormat::Json => {
+            let report = CratesReport {
+                schema_version: SCHEMA_VERSION,
+                identifier: project.identifier.identifier(),
+                crates,
+                total,
+            };
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        OutputFormat::Csv => {
+            let rows: Vec<Vec<String>> = crates
+                .iter()
+                .map(|record| {
+                    vec![
+                        record.name.clone(),
+                        record.builds.to_string(),
+                        record.many_builds.to_string(),
+                        record.usage.apparent_bytes.to_string(),
+                        record.usage.allocated_bytes.to_string(),
+                        record.usage.file_count.to_string(),
+                    ]
+                })
+                .collect();
+            print_csv(
+                &[
+                    "name",
+                    "builds",
+                    "many_builds",
+                    "apparent_bytes",
+                    "allocated_bytes",
+                    "file_count",
+                ],
+                &rows,
+            );
+        }
+        OutputFormat::Table if crate_count == 0 => println!(
+            "Current project '{}' has no cached crate builds",
+            project.identifier.name()
+        ),
+        OutputFormat::Table => {
+            println!("Crates in project '{}' cache:", project.identifier.name());
+            let width = crates
+                .iter()
+                .map(|record| record.name.len())
+                .max()
+                .unwrap_or(0);
+
+            for record in &crates {
+                let size = record.usage.apparent_bytes;
+                let share = if total.apparent_bytes > 0 {
+                    size as f64 / total.apparent_bytes as f64
+                } else {
+                    0.0
+                };
+                let builds = format!(
+                    "{} build{}",
+                    record.builds,
+                    if record.builds == 1 { "" } else { "s" }
+                );
+                let flag = if record.many_builds {
+                    " (many duplicate builds)"
+                } else {
+                    ""
+                };
 
-        for entry in fs::read_dir(target_path)? {
-            let entry = entry?;
-            if entry.file_type()?.is_dir() {
-                let dir_size = calculate_directory_size(&entry.path())?;
-                total_size += dir_size;
                 println!(
-                    "  {}: {}",
-                    entry.file_name().to_string_lossy(),
-                    format_size(dir_size)
+                    "  {:<width$}  {:>10}  {:>5.1}%  {}  {}{}",
+                    record.name,
+                    format_size(size),
+                    share * 100.0,
+                    share_bar(share),
+                    builds,
+                    flag,
+                    width = width
                 );
             }
-        }
 
-        println!("Total cache size: {}", format_size(total_size));
-    } else {
-        println!("No cached target directories found");
+            if crates.len() < crate_count {
+                println!("Showing {} of {} crates", crates.len(), crate_count);
+            }
+            println!("Total crate size: {}", format_size(total.apparent_bytes));
+        }
     }
     Ok(())
 }
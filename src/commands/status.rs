@@ -0,0 +1,166 @@
+use crate::config::{Setting, resolve_settings};
+use crate::registry::Registry;
+use crate::roots::{LinkedProfile, linked_profile_dirs, project_caches, select_root};
+use crate::utils::{
+    TargetLinkState, calculate_directory_size, format_size, get_cargo_path, get_target_link_name,
+    inspect_target_symlink, resolve_project,
+};
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// Everything rcargo resolves for the current directory.
+#[derive(Serialize)]
+struct StatusReport {
+    workspace_root: PathBuf,
+    /// Whether `workspace_root` came from `cargo metadata` rather than the
+    /// current directory fallback.
+    from_cargo_metadata: bool,
+    package_name: String,
+    identifier: String,
+    target_root: PathBuf,
+    /// Name of the target root dev builds go to.
+    root_name: String,
+    /// One-based number of the rule that picked the root, if any.
+    root_rule: Option<usize>,
+    /// Whether the root holds the cache already while the rules now pick another.
+    root_kept: bool,
+    cache_path: PathBuf,
+    /// Caches the project has in other target roots, e.g. from release builds.
+    other_caches: Vec<PathBuf>,
+    /// Profile directories of the cache linked to other roots, with their targets.
+    linked_profiles: Vec<LinkedProfile>,
+    cache_exists: bool,
+    cache_size: Option<u64>,
+    /// Whether the cache is protected from cleanup with `rcargo pin`.
+    pinned: bool,
+    /// Whether the cache was demoted to a slower root for being idle.
+    demoted: bool,
+    link_name: String,
+    link_path: PathBuf,
+    link: TargetLinkState,
+    cargo_path: String,
+    settings: Vec<Setting>,
+}
+
+/// Handles the status command describing how the current project is mapped.
+pub fn handle_status_command(json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let cargo_path = get_cargo_path();
+
+    let project = resolve_project(&cargo_path)?;
+    let registry = Registry::load()?;
+    let root = select_root(&project, "dev", registry.get(&project.identifier))?;
+    let cache_path = root.root.cache_dir(&project.identifier);
+    let other_caches = project_caches(&project.identifier)?
+        .into_iter()
+        .map(|cache| cache.path)
+        .filter(|path| *path != cache_path)
+        .collect();
+    let workspace_root = project.path;
+    let cache_exists = cache_path.is_dir();
+    let cache_size = if cache_exists {
+        Some(calculate_directory_size(&cache_path)?)
+    } else {
+        None
+    };
+    let linked_profiles = if cache_exists {
+        linked_profile_dirs(&cache_path)?
+    } else {
+        Vec::new()
+    };
+    let link_name = get_target_link_name();
+
+    let report = StatusReport {
+        link_path: workspace_root.join(&link_name),
+        link: inspect_target_symlink(&workspace_root, &cache_path),
+        package_name: project.identifier.name().to_string(),
+        identifier: project.identifier.identifier(),
+        workspace_root,
+        from_cargo_metadata: project.from_cargo_metadata,
+        target_root: root.root.path,
+        root_name: root.root.name,
+        root_rule: root.rule,
+        root_kept: root.kept,
+        cache_path,
+        other_caches,
+        linked_profiles,
+        cache_exists,
+        cache_size,
+        pinned: registry.is_pinned(&project.identifier.identifier()),
+        demoted: registry
+            .get(&project.identifier)
+            .is_some_and(|record| record.demoted),
+        link_name,
+        cargo_path,
+        settings: resolve_settings(),
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_status(&report);
+    }
+
+    Ok(())
+}
+
+/// Prints the status report in human-readable form.
+fn print_status(report: &StatusReport) {
+    let root_note = if report.from_cargo_metadata {
+        ""
+    } else {
+        " (cargo metadata unavailable, using current directory)"
+    };
+    println!(
+        "Workspace root: {}{}",
+        report.workspace_root.display(),
+        root_note
+    );
+    println!("Package name:   {}", report.package_name);
+    println!("Identifier:     {}", report.identifier);
+    let rule_note = match report.root_rule {
+        _ if report.root_kept => ", kept where the cache is".to_string(),
+        Some(rule) => format!(", rule {}", rule),
+        None => String::new(),
+    };
+    println!(
+        "Target root:    {} ({}{})",
+        report.root_name,
+        report.target_root.display(),
+        rule_note
+    );
+    println!("Cache path:     {}", report.cache_path.display());
+    for other_cache in &report.other_caches {
+        println!("Other cache:    {}", other_cache.display());
+    }
+    for linked in &report.linked_profiles {
+        println!(
+            "Linked profile: {} -> {}",
+            linked.profile_dir.display(),
+            linked.target.display()
+        );
+    }
+    match report.cache_size {
+        Some(size) => println!("Cache:          exists ({})", format_size(size)),
+        None => println!("Cache:          does not exist"),
+    }
+    if report.pinned {
+        println!("Pinned:         yes");
+    }
+    if report.demoted {
+        println!("Demoted:        yes, the next build moves the cache back");
+    }
+    println!(
+        "Target link:    {} ({})",
+        report.link_path.display(),
+        report.link
+    );
+    println!("Cargo binary:   {}", report.cargo_path);
+
+    println!("Configuration:");
+    for setting in &report.settings {
+        println!(
+            "  {} = {} ({}, {})",
+            setting.name, setting.value, setting.source, setting.variable
+        );
+    }
+}
@@ -0,0 +1,320 @@
+use crate::layout::{ProfileDir, TargetLayout, Unit};
+use crate::lock::lock_for_purge;
+use crate::registry::{Registry, SECONDS_PER_DAY, unix_now};
+use crate::roots::{list_caches, project_caches};
+use crate::toolchain::{installed_rustc_versions, read_metadata_rustc_version};
+use crate::utils::{calculate_directory_size, format_size, get_cargo_path, resolve_project};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How often redirected builds reset the access times of their cache.
+pub const ARM_INTERVAL_SECS: u64 = SECONDS_PER_DAY;
+
+/// Resets the access times of every fingerprint file to its modification time.
+///
+/// Called before a redirected build at most once per `ARM_INTERVAL_SECS`. With the common `relatime` mount
+/// option, access times are only updated when they are not newer than the
+/// modification time, so this makes cargo's fingerprint checks during the
+/// following builds reliably mark which units they used.
+pub fn arm_access_times(cache_dir: &Path) -> io::Result<()> {
+    for profile in TargetLayout::scan(cache_dir)?.profiles {
+        for unit in profile.units()? {
+            for entry in fs::read_dir(&unit.fingerprint_dir)? {
+                let path = entry?.path();
+                let modified = fs::metadata(&path)?.modified()?;
+                fs::File::open(&path)?.set_times(fs::FileTimes::new().set_accessed(modified))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Latest time a unit's fingerprint was read or written, in Unix seconds.
+fn last_touched(unit: &Unit) -> io::Result<u64> {
+    let mut latest = SystemTime::UNIX_EPOCH;
+
+    for entry in fs::read_dir(&unit.fingerprint_dir)? {
+        let metadata = entry?.metadata()?;
+        latest = latest.max(metadata.modified()?);
+        if let Ok(accessed) = metadata.accessed() {
+            latest = latest.max(accessed);
+        }
+    }
+
+    Ok(latest
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs())
+}
+
+/// Stale artifacts found in one project cache.
+#[derive(Default)]
+struct SweepPlan {
+    paths: Vec<PathBuf>,
+    size: u64,
+    stale_units: usize,
+    /// Whether any unit was kept as fresh.
+    any_fresh: bool,
+}
+
+impl SweepPlan {
+    /// Marks a unit as stale, taking its artifacts out of `artifacts_by_hash`.
+    fn add_unit(
+        &mut self,
+        unit: &Unit,
+        artifacts_by_hash: &mut HashMap<String, Vec<PathBuf>>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.stale_units += 1;
+        for path in artifacts_by_hash.remove(&unit.hash).unwrap_or_default() {
+            self.size += if path.is_dir() {
+                calculate_directory_size(&path)?
+            } else {
+                fs::metadata(&path)?.len()
+            };
+            self.paths.push(path);
+        }
+        Ok(())
+    }
+}
+
+/// Groups the artifacts of a profile by unit hash.
+fn artifacts_by_hash(profile: &ProfileDir) -> io::Result<HashMap<String, Vec<PathBuf>>> {
+    let mut artifacts_by_hash: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    for artifact in profile.unit_artifacts()? {
+        artifacts_by_hash
+            .entry(artifact.hash)
+            .or_default()
+            .push(artifact.path);
+    }
+    Ok(artifacts_by_hash)
+}
+
+/// Finds artifacts of units not touched since `cutoff`.
+///
+/// The most recently touched unit of each crate and kind is always kept, so a
+/// sweep never forces a full rebuild.
+fn plan_sweep(cache_dir: &Path, cutoff: u64) -> Result<SweepPlan, Box<dyn std::error::Error>> {
+    let mut plan = SweepPlan::default();
+
+    for profile in TargetLayout::scan(cache_dir)?.profiles {
+        let mut artifacts_by_hash = artifacts_by_hash(&profile)?;
+
+        // Keep only the latest unit of each crate and kind
+        let mut latest: HashMap<(String, String), (u64, String)> = HashMap::new();
+        let mut touched = Vec::new();
+        for unit in profile.units()? {
+            let time = last_touched(&unit)?;
+            let key = (unit.name.clone(), unit.kind.clone());
+            if latest
+                .get(&key)
+                .is_none_or(|(latest_time, _)| time > *latest_time)
+            {
+                latest.insert(key, (time, unit.hash.clone()));
+            }
+            touched.push((unit, time));
+        }
+
+        for (unit, time) in touched {
+            if time >= cutoff {
+                plan.any_fresh = true;
+                continue;
+            }
+            let is_latest = latest
+                .get(&(unit.name.clone(), unit.kind.clone()))
+                .is_some_and(|(_, hash)| *hash == unit.hash);
+            if is_latest {
+                continue;
+            }
+
+            plan.add_unit(&unit, &mut artifacts_by_hash)?;
+        }
+    }
+
+    Ok(plan)
+}
+
+/// Finds artifacts of units built by rustc versions not in `installed`.
+///
+/// Versions are read from the crate metadata of library units, then carried
+/// over to every unit whose fingerprint records the same rustc. Units of an
+/// unidentified rustc are kept.
+fn plan_toolchain_sweep(
+    cache_dir: &Path,
+    installed: &BTreeSet<String>,
+) -> Result<SweepPlan, Box<dyn std::error::Error>> {
+    let mut plan = SweepPlan::default();
+
+    for profile in TargetLayout::scan(cache_dir)?.profiles {
+        let mut artifacts_by_hash = artifacts_by_hash(&profile)?;
+
+        let mut units = Vec::new();
+        let mut versions: HashMap<u64, String> = HashMap::new();
+        for unit in profile.units()? {
+            let Some(rustc_hash) = unit.rustc_hash()? else {
+                continue;
+            };
+
+            if !versions.contains_key(&rustc_hash) {
+                let metadata = artifacts_by_hash
+                    .get(&unit.hash)
+                    .into_iter()
+                    .flatten()
+                    .filter(|path| {
+                        path.extension()
+                            .is_some_and(|extension| extension == "rmeta" || extension == "rlib")
+                    });
+                for path in metadata {
+                    if let Some(version) = read_metadata_rustc_version(path)? {
+                        versions.insert(rustc_hash, version);
+                        break;
+                    }
+                }
+            }
+            units.push((unit, rustc_hash));
+        }
+
+        for (unit, rustc_hash) in units {
+            let is_stale = versions
+                .get(&rustc_hash)
+                .is_some_and(|version| !installed.contains(version));
+            if is_stale {
+                plan.add_unit(&unit, &mut artifacts_by_hash)?;
+            } else {
+                plan.any_fresh = true;
+            }
+        }
+    }
+
+    Ok(plan)
+}
+
+/// Handles the sweep command, removing stale dependency builds from caches.
+///
+/// Without `older_than_days`, artifacts not touched since the access times
+/// were last reset are removed, i.e. those no build of about the last day of
+/// use needed. With `toolchain_stale`,
+/// artifacts built by rustc versions that are no longer installed are removed
+/// instead.
+pub fn handle_sweep_command(
+    all: bool,
+    older_than_days: Option<u64>,
+    toolchain_stale: bool,
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let caches = if all {
+        list_caches()?
+    } else {
+        project_caches(&resolve_project(&get_cargo_path())?.identifier)?
+    };
+
+    if caches.is_empty() {
+        println!("No cached target directories found to sweep");
+        return Ok(());
+    }
+
+    let installed = if toolchain_stale {
+        let installed = installed_rustc_versions(&get_cargo_path());
+        if installed.is_empty() {
+            return Err("Could not determine the installed rustc versions".into());
+        }
+        installed
+    } else {
+        BTreeSet::new()
+    };
+
+    let registry = Registry::load()?;
+    let mut total_freed = 0;
+
+    for cache in caches {
+        let cache_dir = &cache.path;
+        let identifier = &cache.identifier;
+
+        let cutoff = match older_than_days {
+            _ if toolchain_stale => 0,
+            Some(days) => unix_now().saturating_sub(days * SECONDS_PER_DAY),
+            None => match registry
+                .projects
+                .get(identifier)
+                .and_then(|record| record.access_times_armed)
+            {
+                Some(armed) => armed,
+                None => {
+                    println!(
+                        "  Skipped '{}': no build recorded by rcargo (use --older-than)",
+                        cache
+                    );
+                    continue;
+                }
+            },
+        };
+
+        // Sweeping under a running build would delete what it is producing
+        let _lock = match lock_for_purge(cache_dir, false)? {
+            Ok(lock) => lock,
+            Err(busy) => {
+                println!("  Skipped '{}': {}", cache, busy);
+                continue;
+            }
+        };
+
+        let plan = if toolchain_stale {
+            plan_toolchain_sweep(cache_dir, &installed)?
+        } else {
+            plan_sweep(cache_dir, cutoff)?
+        };
+
+        // Nothing touched since the times were reset means access times are
+        // not being recorded, and every unit would look stale
+        if older_than_days.is_none() && !toolchain_stale && !plan.any_fresh && plan.stale_units > 0
+        {
+            println!(
+                "  Skipped '{}': no artifacts were accessed by recent builds, access times may not be recorded on this filesystem (use --older-than)",
+                cache
+            );
+            continue;
+        }
+
+        if plan.stale_units == 0 {
+            println!("  '{}': nothing to sweep", cache);
+            continue;
+        }
+
+        if dry_run {
+            println!(
+                "  '{}': would remove {} stale units ({})",
+                cache,
+                plan.stale_units,
+                format_size(plan.size)
+            );
+            for path in &plan.paths {
+                let relative = path.strip_prefix(cache_dir).unwrap_or(path);
+                println!("    {}", relative.display());
+            }
+        } else {
+            for path in &plan.paths {
+                if path.is_dir() {
+                    fs::remove_dir_all(path)?;
+                } else {
+                    fs::remove_file(path)?;
+                }
+            }
+            println!(
+                "  '{}': removed {} stale units (freed {})",
+                cache,
+                plan.stale_units,
+                format_size(plan.size)
+            );
+        }
+        total_freed += plan.size;
+    }
+
+    if dry_run {
+        println!("Would free {}", format_size(total_freed));
+    } else {
+        println!("Sweep freed {}", format_size(total_freed));
+    }
+    Ok(())
+}
@@ -0,0 +1,255 @@
+use crate::demote::{DemoteConfig, demotion};
+use crate::get_target_dir;
+use crate::memory::{get_low_memory_policy, get_max_swap_used, get_min_available_memory};
+use crate::registry::get_state_dir;
+use crate::roots::{RootRule, TargetRoot, target_roots};
+use crate::space::{
+    get_fallback_target_dir, get_low_space_policy, get_min_free_space, non_empty_var,
+};
+use crate::utils::{
+    format_size, get_cargo_path, get_target_link_name, is_cargo_subcommand, is_ram_expected,
+    is_target_link_disabled,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Where the effective value of a setting came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigSource {
+    /// Built-in default value.
+    Default,
+    /// An `RCARGO_*` environment variable.
+    Environment,
+    /// The `$CARGO` variable set by cargo when running as `cargo rcargo`.
+    CargoSubcommand,
+    /// The rcargo configuration file.
+    ConfigFile,
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "default"),
+            ConfigSource::Environment => write!(f, "environment"),
+            ConfigSource::CargoSubcommand => write!(f, "cargo subcommand"),
+            ConfigSource::ConfigFile => write!(f, "config file"),
+        }
+    }
+}
+
+/// A setting with its effective value and the source it was resolved from.
+#[derive(Debug, Clone, Serialize)]
+pub struct Setting {
+    pub name: &'static str,
+    pub variable: &'static str,
+    pub value: String,
+    pub source: ConfigSource,
+}
+
+impl Setting {
+    fn from_env(name: &'static str, variable: &'static str, value: String) -> Self {
+        let source = if env::var_os(variable).is_some() {
+            ConfigSource::Environment
+        } else {
+            ConfigSource::Default
+        };
+
+        Setting {
+            name,
+            variable,
+            value,
+            source,
+        }
+    }
+}
+
+/// Settings read from the rcargo configuration file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Target roots in order of preference.
+    #[serde(default)]
+    pub roots: Vec<TargetRoot>,
+    /// Rules picking a root per build, tried in order.
+    #[serde(default)]
+    pub rules: Vec<RootRule>,
+    /// Roots the output directories of some profiles are moved to, by profile.
+    #[serde(default)]
+    pub profile_roots: BTreeMap<String, String>,
+    /// Moving idle caches to a slower root.
+    #[serde(default)]
+    pub demote: Option<DemoteConfig>,
+}
+
+static CONFIG_FILE: OnceLock<Result<ConfigFile, String>> = OnceLock::new();
+
+/// Gets the path of the configuration file from `RCARGO_CONFIG`, defaulting to
+/// `rcargo/config.toml` in the XDG config directory.
+pub fn get_config_path() -> PathBuf {
+    if let Some(path) = non_empty_var("RCARGO_CONFIG") {
+        return PathBuf::from(path);
+    }
+    let config_home = non_empty_var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_else(|| PathBuf::from(".config"));
+    config_home.join("rcargo").join("config.toml")
+}
+
+/// Loads the configuration file once, treating a missing file as empty.
+pub fn config_file() -> Result<&'static ConfigFile, String> {
+    CONFIG_FILE
+        .get_or_init(|| {
+            let path = get_config_path();
+            match fs::read_to_string(&path) {
+                Ok(content) => toml::from_str(&content)
+                    .map_err(|e| format!("Failed to parse config '{}': {}", path.display(), e)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(ConfigFile::default()),
+                Err(e) => Err(format!("Failed to read config '{}': {}", path.display(), e)),
+            }
+        })
+        .as_ref()
+        .map_err(Clone::clone)
+}
+
+/// Resolves every setting rcargo reads, together with the source of its value.
+pub fn resolve_settings() -> Vec<Setting> {
+    let cargo_path = if is_cargo_subcommand() && env::var_os("CARGO").is_some() {
+        Setting {
+            name: "cargo_path",
+            variable: "CARGO",
+            value: get_cargo_path(),
+            source: ConfigSource::CargoSubcommand,
+        }
+    } else {
+        Setting::from_env("cargo_path", "RCARGO_CARGO_PATH", get_cargo_path())
+    };
+
+    let roots_source = if env::var_os("RCARGO_TARGET_DIR").is_some() {
+        ConfigSource::Environment
+    } else if config_file().is_ok_and(|config| !config.roots.is_empty()) {
+        ConfigSource::ConfigFile
+    } else {
+        ConfigSource::Default
+    };
+    let target_roots = Setting {
+        name: "target_roots",
+        variable: "RCARGO_TARGET_DIR",
+        value: match target_roots() {
+            Ok(roots) => roots
+                .iter()
+                .map(|root| format!("{}={}", root.name, root.path.display()))
+                .collect::<Vec<_>>()
+                .join(", "),
+            Err(e) => format!("invalid ({})", e),
+        },
+        source: roots_source,
+    };
+
+    vec![
+        cargo_path,
+        Setting::from_env(
+            "config_file",
+            "RCARGO_CONFIG",
+            get_config_path().display().to_string(),
+        ),
+        Setting {
+            name: "target_dir",
+            variable: "RCARGO_TARGET_DIR",
+            value: get_target_dir().to_string(),
+            source: roots_source,
+        },
+        target_roots,
+        Setting {
+            name: "demote",
+            variable: "RCARGO_CONFIG",
+            value: match demotion() {
+                Ok(Some(demotion)) => demotion.to_string(),
+                Ok(None) => "off".to_string(),
+                Err(e) => format!("invalid ({})", e),
+            },
+            source: if config_file().is_ok_and(|config| config.demote.is_some()) {
+                ConfigSource::ConfigFile
+            } else {
+                ConfigSource::Default
+            },
+        },
+        Setting::from_env(
+            "no_target_link",
+            "RCARGO_NO_TARGET_LINK",
+            is_target_link_disabled().to_string(),
+        ),
+        Setting::from_env(
+            "target_link_name",
+            "RCARGO_TARGET_LINK_NAME",
+            get_target_link_name(),
+        ),
+        Setting::from_env(
+            "expect_ram",
+            "RCARGO_EXPECT_RAM",
+            is_ram_expected().to_string(),
+        ),
+        Setting::from_env(
+            "state_dir",
+            "RCARGO_STATE_DIR",
+            get_state_dir().display().to_string(),
+        ),
+        Setting::from_env(
+            "min_free_space",
+            "RCARGO_MIN_FREE_SPACE",
+            match get_min_free_space() {
+                Ok(Some(size)) => format_size(size),
+                Ok(None) => "none".to_string(),
+                Err(e) => format!("invalid ({})", e),
+            },
+        ),
+        Setting::from_env(
+            "low_space_policy",
+            "RCARGO_LOW_SPACE_POLICY",
+            match get_low_space_policy() {
+                Ok(policy) => policy.to_string(),
+                Err(e) => format!("invalid ({})", e),
+            },
+        ),
+        Setting::from_env(
+            "min_available_memory",
+            "RCARGO_MIN_AVAILABLE_MEMORY",
+            match get_min_available_memory() {
+                Ok(Some(size)) => format_size(size),
+                Ok(None) => "none".to_string(),
+                Err(e) => format!("invalid ({})", e),
+            },
+        ),
+        Setting::from_env(
+            "max_swap_used",
+            "RCARGO_MAX_SWAP_USED",
+            match get_max_swap_used() {
+                Ok(Some(size)) => format_size(size),
+                Ok(None) => "none".to_string(),
+                Err(e) => format!("invalid ({})", e),
+            },
+        ),
+        Setting::from_env(
+            "low_memory_policy",
+            "RCARGO_LOW_MEMORY_POLICY",
+            match get_low_memory_policy() {
+                Ok(policy) => policy.to_string(),
+                Err(e) => format!("invalid ({})", e),
+            },
+        ),
+        Setting::from_env(
+            "fallback_target_dir",
+            "RCARGO_FALLBACK_TARGET_DIR",
+            get_fallback_target_dir()
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_else(|| "none".to_string()),
+        ),
+    ]
+}
@@ -0,0 +1,302 @@
+use crate::config::config_file;
+use crate::lock::{CACHE_LOCK_NAME, lock_for_purge, remove_if_emptied};
+use crate::plan::Redirect;
+use crate::registry::{ProjectRecord, Registry, SECONDS_PER_DAY, unix_now};
+use crate::roots::{RootSelection, TargetRoot, routed_profile_dirs, target_roots};
+use crate::space::get_min_free_space;
+use crate::utils::{
+    ProjectIdentifier, TargetLinkState, create_target_symlink, filesystem_space, format_size,
+    inspect_target_symlink, list_project_caches, measure_directory, merge_directory,
+    spawn_detached,
+};
+use serde::Deserialize;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+/// The `[demote]` table of the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DemoteConfig {
+    /// Root idle caches are moved out of, the first root by default.
+    pub from: Option<String>,
+    /// Root idle caches are moved to.
+    pub to: String,
+    /// Days since the last build after which a cache counts as idle.
+    pub after_days: u64,
+}
+
+/// Resolved demotion settings.
+#[derive(Debug, Clone, Copy)]
+pub struct Demotion {
+    pub from: &'static TargetRoot,
+    pub to: &'static TargetRoot,
+    pub after_days: u64,
+}
+
+impl fmt::Display for Demotion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} -> {} after {} days",
+            self.from.name, self.to.name, self.after_days
+        )
+    }
+}
+
+/// Gets the demotion settings, if caches are demoted at all.
+///
+/// `RCARGO_TARGET_DIR` disables demotion along with the configured roots.
+pub fn demotion() -> Result<Option<Demotion>, String> {
+    if env::var_os("RCARGO_TARGET_DIR").is_some() {
+        return Ok(None);
+    }
+    let Some(demote) = &config_file()?.demote else {
+        return Ok(None);
+    };
+
+    // Both roots were checked against the configured ones
+    let roots = target_roots()?;
+    let find = |name: &str| roots.iter().find(|root| root.name == name);
+    let from = match &demote.from {
+        Some(name) => find(name),
+        None => roots.first(),
+    };
+    match (from, find(&demote.to)) {
+        (Some(from), Some(to)) => Ok(Some(Demotion {
+            from,
+            to,
+            after_days: demote.after_days,
+        })),
+        _ => Ok(None),
+    }
+}
+
+/// Seconds between two looks for idle caches; caches turn idle after days.
+const DEMOTE_INTERVAL_SECS: u64 = SECONDS_PER_DAY;
+
+/// Starts `rcargo demote-idle` in a detached process when demotion is
+/// configured, so builds do not wait for idle caches to be moved.
+///
+/// Only the first build every `DEMOTE_INTERVAL_SECS` starts one.
+pub fn spawn_demoter(keep: &ProjectIdentifier) -> Result<(), Box<dyn std::error::Error>> {
+    if demotion()?.is_none() {
+        return Ok(());
+    }
+    let due = Registry::update(|registry| {
+        let now = unix_now();
+        let due = registry
+            .demoter_started
+            .is_none_or(|started| now.saturating_sub(started) >= DEMOTE_INTERVAL_SECS);
+        if due {
+            registry.demoter_started = Some(now);
+        }
+        due
+    })?;
+    if due {
+        spawn_detached(["demote-idle".to_string(), keep.identifier()])?;
+    }
+    Ok(())
+}
+
+/// Moves the caches of projects not built for `after_days` out of the fast
+/// root, sparing the project about to build, pinned caches and caches in use.
+///
+/// Caches rcargo never built have no known age and stay where they are.
+pub fn demote_idle_caches(keep: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(demotion) = demotion()? else {
+        return Ok(());
+    };
+    if !demotion.from.path.is_dir() {
+        return Ok(());
+    }
+
+    let registry = Registry::load()?;
+    let cutoff = unix_now().saturating_sub(demotion.after_days * SECONDS_PER_DAY);
+
+    for cache_dir in list_project_caches(&demotion.from.path)? {
+        let identifier = cache_dir
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        if identifier == keep || !is_idle(registry.projects.get(&identifier), cutoff) {
+            continue;
+        }
+        // Another demoter may have moved the cache since it was listed, and
+        // builds hold the lock from before they promote the cache
+        let Ok(Ok(lock)) = lock_for_purge(&cache_dir, false) else {
+            continue;
+        };
+        // A build may have used the cache since the registry was loaded
+        let registry = Registry::load()?;
+        let Some(record) = registry.projects.get(&identifier) else {
+            continue;
+        };
+        if !is_idle(Some(record), cutoff) {
+            continue;
+        }
+
+        let size = measure_directory(&cache_dir)?.allocated_bytes;
+        fs::create_dir_all(&demotion.to.path)?;
+        let available = filesystem_space(&demotion.to.path)?.available;
+        if available < size {
+            eprintln!(
+                "Warning: Not enough space in {} to demote '{}' ({} needed, {} free)",
+                demotion.to,
+                record.name,
+                format_size(size),
+                format_size(available)
+            );
+            continue;
+        }
+
+        // Flagged first, so a demotion cut short is finished by the promotion
+        Registry::update(|registry| {
+            if let Some(record) = registry.projects.get_mut(&identifier) {
+                record.demoted = true;
+            }
+        })?;
+        let demoted_dir = demotion.to.path.join(&identifier);
+        merge_directory(&cache_dir, &demoted_dir, &[PathBuf::from(CACHE_LOCK_NAME)])?;
+        remove_if_emptied(&cache_dir)?;
+        drop(lock);
+
+        println!(
+            "RCargo: Demoted idle cache of '{}' to {} ({})",
+            record.name,
+            demotion.to,
+            format_size(size)
+        );
+
+        // Plain cargo keeps finding the cache through the target link
+        if inspect_target_symlink(&record.path, &cache_dir) == TargetLinkState::Ok
+            && let Err(e) = create_target_symlink(&record.path, &demoted_dir)
+        {
+            eprintln!("Warning: Could not update the target symlink: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks whether a cache is unpinned and its project was last built before
+/// `cutoff`.
+fn is_idle(record: Option<&ProjectRecord>, cutoff: u64) -> bool {
+    record.is_some_and(|record| {
+        !record.pinned && record.last_used.is_some_and(|last_used| last_used < cutoff)
+    })
+}
+
+/// Moves a demoted cache back into the root the build selected, or builds it
+/// where it is when that root is short of space or the move fails.
+///
+/// Profile directories routed to the slower root stay there and are linked.
+/// A cache left demoted is moved back by a later build.
+pub fn promote_cache(redirect: &mut Redirect) {
+    let Some(demoted_root) = redirect.demoted.take() else {
+        return;
+    };
+
+    match move_back(redirect, &demoted_root) {
+        Ok(true) => {}
+        Ok(false) => return build_in_place(redirect, demoted_root),
+        Err(e) => {
+            eprintln!(
+                "Warning: Could not promote the cache, building in {}: {}",
+                demoted_root, e
+            );
+            return build_in_place(redirect, demoted_root);
+        }
+    }
+
+    let cleared = Registry::update(|registry| {
+        registry
+            .record_mut(&redirect.project.identifier, &redirect.project.path)
+            .demoted = false;
+    });
+    if let Err(e) = cleared {
+        eprintln!("Warning: Could not record the promoted cache: {}", e);
+    }
+}
+
+/// Moves a demoted cache into the build's root. Returns false, leaving it in
+/// place, when the root is short of space.
+fn move_back(
+    redirect: &Redirect,
+    demoted_root: &TargetRoot,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let identifier = &redirect.project.identifier;
+    let demoted_dir = demoted_root.cache_dir(identifier);
+    if !demoted_dir.is_dir() {
+        return Ok(true);
+    }
+
+    // Waits for another build promoting the same cache
+    let lock = match lock_for_purge(&demoted_dir, true)? {
+        Ok(lock) => lock,
+        Err(busy) => return Err(busy.to_string().into()),
+    };
+    if !demoted_dir.is_dir() {
+        return Ok(true);
+    }
+
+    let size = measure_directory(&demoted_dir)?.allocated_bytes;
+    let required = size + get_min_free_space()?.unwrap_or(0);
+    let target_root = &redirect.root.root;
+    fs::create_dir_all(&target_root.path)?;
+    let available = filesystem_space(&target_root.path)?.available;
+
+    if available < required {
+        println!(
+            "RCargo: Not enough space in {} to promote the cache ({} needed, {} free), building in {}",
+            target_root,
+            format_size(required),
+            format_size(available),
+            demoted_root
+        );
+        return Ok(false);
+    }
+
+    let routed: Vec<_> = routed_profile_dirs(&demoted_dir, target_root)?
+        .into_iter()
+        .filter(|split| split.root == *demoted_root)
+        .collect();
+    let mut keep = vec![PathBuf::from(CACHE_LOCK_NAME)];
+    keep.extend(
+        routed
+            .iter()
+            .flat_map(|split| split.profile_dirs.iter().cloned()),
+    );
+
+    merge_directory(&demoted_dir, &redirect.cargo_target_dir, &keep)?;
+    for split in &routed {
+        split.link(&redirect.cargo_target_dir, identifier)?;
+    }
+    remove_if_emptied(&demoted_dir)?;
+    drop(lock);
+
+    // Routed profiles stayed behind and do not count
+    let promoted = measure_directory(&redirect.cargo_target_dir)?.allocated_bytes;
+    println!(
+        "RCargo: Promoted cache from {} to {} ({})",
+        demoted_root,
+        target_root,
+        format_size(promoted)
+    );
+    Ok(true)
+}
+
+/// Points a build at the demoted cache, which stays flagged as demoted.
+fn build_in_place(redirect: &mut Redirect, demoted_root: TargetRoot) {
+    if redirect
+        .split
+        .as_ref()
+        .is_some_and(|split| split.root == demoted_root)
+    {
+        redirect.split = None;
+    }
+    redirect.cargo_target_dir = demoted_root.cache_dir(&redirect.project.identifier);
+    redirect.root = RootSelection::fixed(demoted_root);
+}
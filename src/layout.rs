@@ -0,0 +1,340 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Entries whose presence marks a directory as a cargo profile output directory.
+const PROFILE_MARKERS: &[&str] = &[
+    "deps",
+    ".fingerprint",
+    "build",
+    "incremental",
+    ".cargo-lock",
+];
+
+/// A profile output directory inside a cargo target directory, such as
+/// `debug/` or `x86_64-unknown-linux-gnu/release/`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileDir {
+    /// Target triple for cross builds, `None` for host builds.
+    pub triple: Option<String>,
+    /// Directory name of the profile (`debug` for dev/test, `release` for
+    /// release/bench, otherwise the custom profile name).
+    pub profile: String,
+    pub path: PathBuf,
+}
+
+/// The parts of a cargo target directory rcargo understands.
+#[derive(Debug, Default, Clone)]
+pub struct TargetLayout {
+    pub profiles: Vec<ProfileDir>,
+    /// Documentation directories, `doc/` and `<triple>/doc/`.
+    pub docs: Vec<PathBuf>,
+    /// Per-triple directories of cross builds, with their triple.
+    pub triples: Vec<(String, PathBuf)>,
+}
+
+/// Checks whether a directory looks like a cargo profile output directory.
+fn is_profile_dir(path: &Path) -> bool {
+    PROFILE_MARKERS
+        .iter()
+        .any(|marker| path.join(marker).exists())
+}
+
+/// Lists the subdirectories of a directory with their names.
+fn subdirectories(path: &Path) -> io::Result<Vec<(String, PathBuf)>> {
+    let mut dirs = Vec::new();
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            dirs.push((
+                entry.file_name().to_string_lossy().to_string(),
+                entry.path(),
+            ));
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+impl TargetLayout {
+    /// Scans a cargo target directory.
+    ///
+    /// Top-level directories are host profiles or `doc`; any other directory
+    /// holding profile directories is a target triple of a cross build.
+    pub fn scan(target_dir: &Path) -> io::Result<Self> {
+        let mut layout = TargetLayout::default();
+
+        for (name, path) in subdirectories(target_dir)? {
+            if name == "doc" {
+                layout.docs.push(path);
+            } else if is_profile_dir(&path) {
+                layout.profiles.push(ProfileDir {
+                    triple: None,
+                    profile: name,
+                    path,
+                });
+            } else {
+                let mut is_triple = false;
+                for (nested_name, nested_path) in subdirectories(&path)? {
+                    if nested_name == "doc" {
+                        layout.docs.push(nested_path);
+                        is_triple = true;
+                    } else if is_profile_dir(&nested_path) {
+                        layout.profiles.push(ProfileDir {
+                            triple: Some(name.clone()),
+                            profile: nested_name,
+                            path: nested_path,
+                        });
+                        is_triple = true;
+                    }
+                }
+                if is_triple {
+                    layout.triples.push((name, path));
+                }
+            }
+        }
+
+        Ok(layout)
+    }
+}
+
+/// A category of artifacts that can be purged selectively.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArtifactCategory {
+    /// Incremental compilation state of every profile.
+    Incremental,
+    /// Generated documentation.
+    Doc,
+    /// Build script outputs (`build/`) of every profile.
+    BuildScripts,
+    /// A profile directory (`debug`, `release`, custom) or a target triple.
+    Named(String),
+}
+
+impl ArtifactCategory {
+    /// Parses a category given on the command line.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "" => Err("artifact category must not be empty".to_string()),
+            "incremental" => Ok(ArtifactCategory::Incremental),
+            "doc" => Ok(ArtifactCategory::Doc),
+            "build-scripts" => Ok(ArtifactCategory::BuildScripts),
+            name => Ok(ArtifactCategory::Named(name.to_string())),
+        }
+    }
+
+    /// Finds the paths of this category inside a scanned target directory.
+    pub fn paths(&self, layout: &TargetLayout) -> Vec<PathBuf> {
+        let profile_subdirs = |subdir: &str| {
+            layout
+                .profiles
+                .iter()
+                .map(|profile| profile.path.join(subdir))
+                .filter(|path| path.exists())
+                .collect()
+        };
+
+        match self {
+            ArtifactCategory::Incremental => profile_subdirs("incremental"),
+            ArtifactCategory::BuildScripts => profile_subdirs("build"),
+            ArtifactCategory::Doc => layout.docs.clone(),
+            ArtifactCategory::Named(name) => {
+                let triples = layout
+                    .triples
+                    .iter()
+                    .filter(|(triple, _)| triple == name)
+                    .map(|(_, path)| path.clone());
+                let profiles = layout
+                    .profiles
+                    .iter()
+                    .filter(|profile| &profile.profile == name)
+                    .map(|profile| profile.path.clone());
+                triples.chain(profiles).collect()
+            }
+        }
+    }
+}
+
+impl fmt::Display for ArtifactCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArtifactCategory::Incremental => write!(f, "incremental"),
+            ArtifactCategory::Doc => write!(f, "doc"),
+            ArtifactCategory::BuildScripts => write!(f, "build-scripts"),
+            ArtifactCategory::Named(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Splits a cargo `{name}-{hash}` file stem into its name and 16-digit hash.
+pub fn parse_unit_name(stem: &str) -> Option<(&str, &str)> {
+    let (name, hash) = stem.rsplit_once('-')?;
+    let is_hash = hash.len() == 16 && hash.chars().all(|c| c.is_ascii_hexdigit());
+
+    if name.is_empty() || !is_hash {
+        return None;
+    }
+    Some((name, hash))
+}
+
+/// A compilation unit recorded in a profile's `.fingerprint/` directory.
+#[derive(Debug, Clone)]
+pub struct Unit {
+    pub name: String,
+    pub hash: String,
+    /// Fingerprint file names identifying the unit kind and target, e.g.
+    /// `lib-serde` or `build-script-build-script-build`.
+    pub kind: String,
+    pub fingerprint_dir: PathBuf,
+}
+
+impl Unit {
+    /// Reads the hash of the rustc version recorded in the unit's fingerprint.
+    pub fn rustc_hash(&self) -> io::Result<Option<u64>> {
+        for entry in fs::read_dir(&self.fingerprint_dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+
+            let fingerprint: serde_json::Value = match serde_json::from_slice(&fs::read(&path)?) {
+                Ok(fingerprint) => fingerprint,
+                Err(_) => continue,
+            };
+            if let Some(hash) = fingerprint.get("rustc").and_then(|rustc| rustc.as_u64()) {
+                return Ok(Some(hash));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// A file or directory belonging to a compilation unit.
+#[derive(Debug, Clone)]
+pub struct UnitArtifact {
+    pub name: String,
+    pub hash: String,
+    pub path: PathBuf,
+}
+
+/// Profile subdirectories holding per-unit `{name}-{hash}` artifacts.
+const UNIT_ARTIFACT_DIRS: &[&str] = &[".fingerprint", "build", "deps", "examples"];
+
+impl ProfileDir {
+    /// Lists the compilation units fingerprinted in this profile.
+    pub fn units(&self) -> io::Result<Vec<Unit>> {
+        let fingerprint_root = self.path.join(".fingerprint");
+        if !fingerprint_root.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut units = Vec::new();
+        for (dir_name, fingerprint_dir) in subdirectories(&fingerprint_root)? {
+            let Some((name, hash)) = parse_unit_name(&dir_name) else {
+                continue;
+            };
+
+            // The hash files are the ones without a suffix or prefix of their own
+            let mut kinds = Vec::new();
+            for entry in fs::read_dir(&fingerprint_dir)? {
+                let file_name = entry?.file_name().to_string_lossy().to_string();
+                let is_auxiliary = file_name.ends_with(".json")
+                    || file_name.starts_with("dep-")
+                    || file_name.starts_with("output-")
+                    || file_name == "invoked.timestamp";
+                if !is_auxiliary {
+                    kinds.push(file_name);
+                }
+            }
+            kinds.sort();
+
+            units.push(Unit {
+                name: name.to_string(),
+                hash: hash.to_string(),
+                kind: kinds.join(","),
+                fingerprint_dir,
+            });
+        }
+
+        Ok(units)
+    }
+
+    /// Lists every `{name}-{hash}` artifact of this profile in `.fingerprint/`,
+    /// `build/`, `deps/` and `examples/`.
+    ///
+    /// Files such as `libserde-0123456789abcdef.rlib` are matched by the part
+    /// of their name before the first dot.
+    pub fn unit_artifacts(&self) -> io::Result<Vec<UnitArtifact>> {
+        let mut artifacts = Vec::new();
+
+        for dir in UNIT_ARTIFACT_DIRS {
+            let dir_path = self.path.join(dir);
+            if !dir_path.is_dir() {
+                continue;
+            }
+
+            for entry in fs::read_dir(&dir_path)? {
+                let entry = entry?;
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                let stem = file_name.split('.').next().unwrap_or_default();
+
+                if let Some((name, hash)) = parse_unit_name(stem) {
+                    artifacts.push(UnitArtifact {
+                        name: name.to_string(),
+                        hash: hash.to_string(),
+                        path: entry.path(),
+                    });
+                }
+            }
+        }
+
+        Ok(artifacts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "0123456789abcdef";
+
+    #[test]
+    fn unit_names_split_at_the_last_hyphen() {
+        assert_eq!(
+            parse_unit_name(&format!("serde-{HASH}")),
+            Some(("serde", HASH))
+        );
+        assert_eq!(
+            parse_unit_name(&format!("serde-json-{HASH}")),
+            Some(("serde-json", HASH))
+        );
+        assert_eq!(
+            parse_unit_name(&format!("libserde_json-{HASH}")),
+            Some(("libserde_json", HASH))
+        );
+    }
+
+    #[test]
+    fn build_script_units_are_recognised() {
+        assert_eq!(
+            parse_unit_name(&format!("build-script-build-{HASH}")),
+            Some(("build-script-build", HASH))
+        );
+        assert_eq!(
+            parse_unit_name(&format!("build_script_build-{HASH}")),
+            Some(("build_script_build", HASH))
+        );
+    }
+
+    #[test]
+    fn unknown_files_are_ignored() {
+        assert_eq!(parse_unit_name("invoked"), None);
+        assert_eq!(parse_unit_name(".cargo-lock"), None);
+        assert_eq!(parse_unit_name("build-script-build"), None);
+        assert_eq!(parse_unit_name(&format!("-{HASH}")), None);
+        assert_eq!(parse_unit_name("serde-0123456789abcde"), None);
+        assert_eq!(parse_unit_name("serde-0123456789abcdeg"), None);
+        assert_eq!(parse_unit_name("serde-0123456789abcdef0"), None);
+    }
+}
@@ -0,0 +1,541 @@
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::process::{Command, exit};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+mod commands;
+mod config;
+mod demote;
+mod layout;
+mod lock;
+mod memory;
+mod plan;
+mod registry;
+mod report;
+mod roots;
+mod space;
+mod toolchain;
+mod trash;
+mod utils;
+
+use commands::purge::{ConfirmationRequired, EXIT_CONFIRMATION_REQUIRED};
+use commands::size::SizeSort;
+use commands::sweep::{ARM_INTERVAL_SECS, arm_access_times};
+use commands::{
+    handle_adopt_command, handle_eject_command, handle_explain_command, handle_links_command,
+    handle_pin_command, handle_purge_command, handle_size_command, handle_status_command,
+    handle_sweep_command,
+};
+use demote::{demote_idle_caches, promote_cache, spawn_demoter};
+use layout::ArtifactCategory;
+use lock::{BuildLock, lock_for_purge, remove_if_emptied};
+use memory::ensure_free_memory;
+use plan::{ExecutionPlan, Redirect};
+use registry::{Registry, SECONDS_PER_DAY, unix_now};
+use report::OutputFormat;
+use roots::target_roots;
+use space::ensure_free_space;
+use trash::{delete_batch, empty_trash};
+use utils::{
+    create_target_symlink, get_cargo_path, is_cargo_subcommand, measure_directory, parse_size,
+};
+
+/// Default target directory location when `RCARGO_TARGET_DIR` is not set.
+///
+/// This directory is typically located on a RAM disk or fast storage to
+/// improve compilation performance. Users can override this location by
+/// setting the `RCARGO_TARGET_DIR` environment variable.
+const DEFAULT_TARGET_DIR: &str = "/tmp/rcargo_targets";
+
+/// Global target directory that is computed once and cached for performance
+static TARGET_DIR: OnceLock<String> = OnceLock::new();
+
+/// Runs rcargo as the `rcargo` or `cargo-rcargo` binary, exiting with the
+/// status of cargo or of the failed command.
+pub fn run() {
+    let cli = parse_cli();
+
+    // Check if version was requested
+    if cli.show_version {
+        print_version();
+        return;
+    }
+
+    if let Err(e) = run_rcargo(cli) {
+        eprintln!("Error: {}", e);
+        if e.is::<ConfirmationRequired>() {
+            exit(EXIT_CONFIRMATION_REQUIRED);
+        }
+        exit(1);
+    }
+}
+
+/// Parses the command line, accounting for cargo's external subcommand convention.
+///
+/// `cargo rcargo build` makes cargo execute `cargo-rcargo rcargo build`, so the
+/// injected `rcargo` argument is dropped and the rest is parsed exactly like a
+/// direct `rcargo build` invocation.
+fn parse_cli() -> Cli {
+    let mut args: Vec<OsString> = env::args_os().collect();
+    if !is_cargo_subcommand() {
+        return Cli::parse_from(args);
+    }
+
+    args.remove(1);
+    let matches = Cli::command()
+        .bin_name("cargo rcargo")
+        .get_matches_from(args);
+    Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+}
+
+/// Command line interface definition for rcargo.
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+#[command(disable_version_flag = true)]
+#[command(
+    about = "A wrapper for Rust's cargo to use a per-project target directory on a fast storage"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Commands>,
+
+    /// Show version information
+    #[arg(short = 'V', long = "version")]
+    pub show_version: bool,
+
+    /// Cargo arguments (when no subcommand is provided)
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    pub cargo_args: Vec<String>,
+}
+
+/// Available subcommands for rcargo.
+#[derive(Subcommand)]
+pub enum Commands {
+    /// Show target directory sizes
+    Size(SizeArgs),
+    /// Purge cached target directories
+    Purge(PurgeArgs),
+    /// Remove stale dependency builds, keeping the latest build of each crate
+    Sweep {
+        /// Sweep all cached projects
+        #[arg(short, long)]
+        all: bool,
+
+        /// Remove artifacts not used for this many days, instead of those not
+        /// used by recent builds
+        #[arg(long, value_name = "DAYS")]
+        older_than: Option<u64>,
+
+        /// Remove artifacts built by rustc versions that are no longer installed
+        #[arg(long, conflicts_with = "older_than")]
+        toolchain_stale: bool,
+
+        /// List what would be removed without removing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Protect the current project's cache from purges and automatic cleanup
+    Pin,
+    /// Stop protecting the current project's cache
+    Unpin,
+    /// List target symlinks created by rcargo
+    Links {
+        /// Fix broken links: recreate empty caches behind `target` links, remove others
+        #[arg(long)]
+        prune: bool,
+    },
+    /// Show how the current project is mapped to its cached target directory
+    Status {
+        /// Print the status as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Move an existing in-tree `target` directory into the cache and link it
+    Adopt {
+        /// Show what would be moved without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Move the project's cache back in-tree and stop redirecting its builds
+    Eject {
+        /// Destination directory (defaults to `target` in the project root)
+        #[arg(long, value_name = "PATH")]
+        to: Option<PathBuf>,
+
+        /// Copy the cache instead of moving it
+        #[arg(long)]
+        copy: bool,
+    },
+    /// Delete a batch of purged files from the trash (run in the background by purge)
+    #[command(hide = true)]
+    DeleteTrash {
+        /// Seconds to wait before deleting, during which the purge can be undone
+        #[arg(long, default_value_t = 0)]
+        grace: u64,
+
+        batch: PathBuf,
+    },
+    /// Move idle caches to the slower root (run in the background by builds)
+    #[command(hide = true)]
+    DemoteIdle {
+        /// Identifier of the project being built, whose cache stays
+        keep: String,
+    },
+    /// Explain how a cargo command would be run, without running it
+    Explain {
+        /// Cargo arguments to explain
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        cargo_args: Vec<String>,
+    },
+}
+
+/// Arguments of the size command.
+#[derive(Args)]
+pub struct SizeArgs {
+    /// Show all cached project target sizes
+    #[arg(short, long)]
+    pub all: bool,
+
+    /// Order of the listing
+    #[arg(long, value_enum, default_value_t = SizeSort::Size)]
+    pub sort: SizeSort,
+
+    /// Reverse the order of the listing
+    #[arg(short, long)]
+    pub reverse: bool,
+
+    /// Only show the first N caches of the listing
+    #[arg(long, value_name = "N")]
+    pub top: Option<usize>,
+
+    /// Only show caches taking at least this much disk space, e.g. 500MiB or 2G
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub min_size: Option<u64>,
+
+    /// Split each cache into profiles, target triples and their parts
+    #[arg(long)]
+    pub breakdown: bool,
+
+    /// List the space used by each crate in the current project's cache
+    #[arg(long, conflicts_with_all = ["all", "breakdown"])]
+    pub crates: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+}
+
+/// Arguments of the purge command.
+#[derive(Args)]
+pub struct PurgeArgs {
+    /// Purge caches whose identifier or project name matches these globs
+    #[arg(value_name = "PATTERN")]
+    pub patterns: Vec<String>,
+
+    /// Purge the cache of the project at this path
+    #[arg(long = "path", value_name = "PATH")]
+    pub paths: Vec<PathBuf>,
+
+    /// Purge all cached project target directories
+    #[arg(short, long)]
+    pub all: bool,
+
+    /// Skip confirmation prompt
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// Wait for running builds to finish instead of skipping their caches
+    #[arg(short, long)]
+    pub wait: bool,
+
+    /// List what would be deleted and how much would be freed, without deleting
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Only purge these artifacts: incremental, doc, build-scripts, or a
+    /// profile (debug, release, ...) or target triple
+    #[arg(long, value_name = "CATEGORY", value_parser = ArtifactCategory::parse)]
+    pub only: Vec<ArtifactCategory>,
+
+    /// Pick the caches to purge from a list with their sizes and last use
+    #[arg(short, long, conflicts_with_all = ["patterns", "paths", "all", "format"])]
+    pub interactive: bool,
+
+    /// Also purge caches pinned with `rcargo pin`
+    #[arg(long)]
+    pub include_pinned: bool,
+
+    /// Restore the most recent purge if its files have not been deleted yet
+    #[arg(
+        long,
+        conflicts_with_all = ["patterns", "paths", "all", "interactive", "dry_run", "only", "format"]
+    )]
+    pub undo: bool,
+
+    /// Output format; json and csv need --yes or --dry-run
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+}
+
+// Gets the preferred target root, computing it once and caching the result.
+pub fn get_target_dir() -> &'static str {
+    TARGET_DIR.get_or_init(|| match roots::target_roots() {
+        Ok(roots) => roots[0].path.to_string_lossy().to_string(),
+        Err(_) => env::var("RCARGO_TARGET_DIR").unwrap_or_else(|_| DEFAULT_TARGET_DIR.to_string()),
+    })
+}
+
+/// Prints version information for both rcargo and the underlying cargo tool.
+///
+/// This function displays the rcargo version from the package metadata and
+/// attempts to get and display the cargo version by executing `cargo --version`.
+///
+/// # Examples
+///
+/// ```text
+/// rcargo 0.1.0
+/// cargo 1.75.0 (1d8b05cdd 2023-11-20)
+/// ```
+fn print_version() {
+    // Print rcargo version
+    println!("rcargo {}", env!("CARGO_PKG_VERSION"));
+
+    let cargo_path = get_cargo_path();
+
+    // Print cargo version
+    match Command::new(cargo_path).arg("--version").output() {
+        Ok(output) => {
+            if output.status.success() {
+                let cargo_version = String::from_utf8_lossy(&output.stdout);
+                print!("{}", cargo_version);
+            } else {
+                eprintln!("Failed to get cargo version");
+            }
+        }
+        Err(e) => {
+            eprintln!("Failed to execute cargo --version: {}", e);
+        }
+    }
+}
+
+/// Seconds between measurements of a project's cache after its builds.
+const SIZE_INTERVAL_SECS: u64 = SECONDS_PER_DAY;
+
+/// Stores the size of a project's cache in the registry, unless it was
+/// measured within the last `SIZE_INTERVAL_SECS`.
+///
+/// Walking a large cache takes a while, and the size only serves as an
+/// estimate for root rules and free space checks.
+fn record_cache_size(redirect: &Redirect) -> Result<(), Box<dyn std::error::Error>> {
+    let now = unix_now();
+    let measured = Registry::load_or_default()
+        .get(&redirect.project.identifier)
+        .and_then(|record| record.last_size_measured);
+    if measured.is_some_and(|measured| now.saturating_sub(measured) < SIZE_INTERVAL_SECS) {
+        return Ok(());
+    }
+
+    let size = measure_directory(&redirect.cargo_target_dir)?.allocated_bytes;
+    Registry::update(|registry| {
+        let record = registry.record_mut(&redirect.project.identifier, &redirect.project.path);
+        record.last_size = Some(size);
+        record.last_size_measured = Some(now);
+    })
+}
+
+// Executes the main rcargo functionality based on parsed command line arguments.
+fn run_rcargo(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    // Get the cargo path from environment or use default
+    let cargo_path = get_cargo_path();
+
+    // Handle rcargo-specific subcommands
+    if let Some(command) = cli.command {
+        match command {
+            Commands::Size(args) => {
+                return handle_size_command(&args);
+            }
+            Commands::Purge(args) => {
+                return handle_purge_command(&args);
+            }
+            Commands::Sweep {
+                all,
+                older_than,
+                toolchain_stale,
+                dry_run,
+            } => {
+                return handle_sweep_command(all, older_than, toolchain_stale, dry_run);
+            }
+            Commands::Pin => {
+                return handle_pin_command(true);
+            }
+            Commands::Unpin => {
+                return handle_pin_command(false);
+            }
+            Commands::Links { prune } => {
+                return handle_links_command(prune);
+            }
+            Commands::Status { json } => {
+                return handle_status_command(json);
+            }
+            Commands::Adopt { dry_run } => {
+                return handle_adopt_command(dry_run);
+            }
+            Commands::Eject { to, copy } => {
+                return handle_eject_command(to, copy);
+            }
+            Commands::Explain { cargo_args } => {
+                return handle_explain_command(cargo_args);
+            }
+            Commands::DeleteTrash { grace, batch } => {
+                return Ok(delete_batch(&batch, Duration::from_secs(grace))?);
+            }
+            Commands::DemoteIdle { keep } => {
+                return demote_idle_caches(&keep);
+            }
+        }
+    }
+
+    // If no subcommand, proceed with normal cargo execution
+    let mut plan = ExecutionPlan::new(cargo_path, cli.cargo_args)?;
+
+    // Only build commands get a cached target directory, others run directly
+    let mut _build_lock = None;
+    let mut _split_lock = None;
+    if let Some(redirect) = &mut plan.redirect {
+        // Trash left behind by interrupted deletions, or all of it when space is low
+        for root in target_roots()? {
+            if let Err(e) = empty_trash(&root.path) {
+                eprintln!("Warning: Could not empty the rcargo trash: {}", e);
+            }
+        }
+
+        // Idle caches leave the fast root in the background, and this
+        // project's comes back
+        if let Err(e) = spawn_demoter(&redirect.project.identifier) {
+            eprintln!("Warning: Could not start demoting idle caches: {}", e);
+        }
+
+        // Keep the build out of RAM while the machine is short of memory
+        ensure_free_memory(redirect)?;
+
+        // Make room in the final root, the fallback one included, before
+        // cargo runs out of space halfway through the build
+        ensure_free_space(redirect)?;
+
+        // A build sent to the fallback root leaves the demoted cache where it is
+        if redirect.fallback_reason.is_some() {
+            redirect.demoted = None;
+        }
+
+        // Keep purges and demoters away from the cache until cargo exits,
+        // creating it if it doesn't exist
+        let mut build_lock = BuildLock::acquire(&redirect.cargo_target_dir)?;
+
+        let selected_dir = redirect.cargo_target_dir.clone();
+        promote_cache(redirect);
+        if redirect.cargo_target_dir != selected_dir {
+            // The cache stayed demoted, so the build runs in the root holding it
+            ensure_free_space(redirect)?;
+            build_lock = BuildLock::acquire(&redirect.cargo_target_dir)?;
+            if let Ok(Ok(_lock)) = lock_for_purge(&selected_dir, false)
+                && let Err(e) = remove_if_emptied(&selected_dir)
+            {
+                eprintln!(
+                    "Warning: Could not remove '{}': {}",
+                    selected_dir.display(),
+                    e
+                );
+            }
+        }
+        _build_lock = Some(build_lock);
+
+        // Routed profiles build through a link into the other root's cache
+        if let Some(split) = &redirect.split {
+            let split_dir = split.root.cache_dir(&redirect.project.identifier);
+            std::fs::create_dir_all(&split_dir)?;
+            _split_lock = Some(BuildLock::acquire(&split_dir)?);
+            split.link(&redirect.cargo_target_dir, &redirect.project.identifier)?;
+        }
+
+        // Create target symlink after successful execution
+        if let Err(e) = create_target_symlink(&redirect.project.path, &redirect.cargo_target_dir) {
+            eprintln!("Warning: Could not create target symlink: {}", e);
+        }
+
+        // Remember the project for registry-based commands, never failing the build
+        let recorded = Registry::update(|registry| {
+            let now = unix_now();
+            let record = registry.record_mut(&redirect.project.identifier, &redirect.project.path);
+            record.last_used = Some(now);
+            record.track_link(&redirect.project.path, &redirect.cargo_target_dir);
+
+            // Fallback roots and demoted caches are where the build ran this time only
+            if redirect.fallback_reason.is_none() && !record.demoted {
+                record.cache_roots.insert(
+                    redirect.rule_profile.clone(),
+                    redirect.root.root.name.clone(),
+                );
+            }
+
+            // Touching every fingerprint is costly, once a day is enough
+            let arm = record
+                .access_times_armed
+                .is_none_or(|armed| now.saturating_sub(armed) >= ARM_INTERVAL_SECS);
+            if arm {
+                record.access_times_armed = Some(now);
+            }
+            arm
+        });
+        let arm = recorded.unwrap_or_else(|e| {
+            eprintln!("Warning: Could not update the rcargo registry: {}", e);
+            false
+        });
+
+        // Lets `rcargo sweep` tell which units builds from now on use
+        if arm && let Err(e) = arm_access_times(&redirect.cargo_target_dir) {
+            eprintln!("Warning: Could not prepare artifacts for sweeping: {}", e);
+        }
+
+        // Print information message
+        match (&redirect.fallback_reason, &redirect.split) {
+            (Some(reason), _) => println!(
+                "RCargo: Target directory redirected to: {} (fallback root: {})",
+                redirect.cargo_target_dir.display(),
+                reason
+            ),
+            (None, Some(split)) => println!(
+                "RCargo: Target directory redirected to: {} ({})",
+                redirect.cargo_target_dir.display(),
+                split
+            ),
+            (None, None) => println!(
+                "RCargo: Target directory redirected to: {}",
+                redirect.cargo_target_dir.display()
+            ),
+        }
+    }
+
+    // Set environment variable and execute the real cargo command
+    let exit_status = plan.command().status()?;
+
+    // Lets the next build estimate the space it needs if the cache is gone by then
+    if let Some(redirect) = &plan.redirect
+        && exit_status.success()
+        && let Err(e) = record_cache_size(redirect)
+    {
+        eprintln!("Warning: Could not record the cache size: {}", e);
+    }
+
+    if !exit_status.success() {
+        if let Some(code) = exit_status.code() {
+            exit(code);
+        } else {
+            exit(1);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use std::fmt;
+use std::fs::{self, File, TryLockError};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Lock file rcargo holds inside a project cache while cargo builds into it.
+pub const CACHE_LOCK_NAME: &str = ".rcargo-lock";
+
+/// Lock file cargo holds in each profile directory during a build.
+const CARGO_LOCK_NAME: &str = ".cargo-lock";
+
+/// Shared lock on a project cache, held for the duration of a redirected build.
+///
+/// Several builds may hold it at once; it only keeps purges out.
+pub struct BuildLock {
+    _file: File,
+}
+
+impl BuildLock {
+    /// Takes the shared build lock, waiting if the cache is being purged,
+    /// demoted or promoted. Creates the cache if it does not exist.
+    ///
+    /// A cache moved away while the lock was awaited is created again, and
+    /// the lock taken on its new lock file.
+    pub fn acquire(cache_dir: &Path) -> io::Result<Self> {
+        let lock_path = cache_dir.join(CACHE_LOCK_NAME);
+        loop {
+            fs::create_dir_all(cache_dir)?;
+            let file = File::create(&lock_path)?;
+            file.lock_shared()?;
+            if is_same_file(&file, &lock_path)? {
+                return Ok(BuildLock { _file: file });
+            }
+        }
+    }
+}
+
+/// Checks whether an open file is still the one at `path`.
+fn is_same_file(file: &File, path: &Path) -> io::Result<bool> {
+    let current = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let opened = file.metadata()?;
+        Ok(opened.dev() == current.dev() && opened.ino() == current.ino())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (file, current);
+        Ok(true)
+    }
+}
+
+/// Removes a cache left with nothing but its lock file, e.g. once its linked
+/// profile directories were moved out; returns whether it was removed.
+pub fn remove_if_emptied(cache_dir: &Path) -> io::Result<bool> {
+    for entry in fs::read_dir(cache_dir)? {
+        if entry?.file_name() != CACHE_LOCK_NAME {
+            return Ok(false);
+        }
+    }
+    fs::remove_dir_all(cache_dir)?;
+    Ok(true)
+}
+
+/// Exclusive lock on a project cache, proving no build is using it.
+pub struct PurgeLock {
+    _file: Option<File>,
+}
+
+/// Why a project cache cannot be purged right now.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheBusy {
+    /// An rcargo-redirected build holds the cache.
+    Rcargo,
+    /// Cargo holds the lock of a profile directory.
+    Cargo(PathBuf),
+}
+
+impl fmt::Display for CacheBusy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CacheBusy::Rcargo => write!(f, "an rcargo build is running in it"),
+            CacheBusy::Cargo(lock_path) => {
+                write!(f, "cargo is holding '{}'", lock_path.display())
+            }
+        }
+    }
+}
+
+/// Locks a project cache for purging.
+///
+/// Takes rcargo's own lock exclusively, then checks cargo's `.cargo-lock` files
+/// in every profile directory, which also catches builds started with plain
+/// cargo through the target symlink. With `wait`, blocks until every lock is
+/// free; otherwise reports the first busy lock.
+pub fn lock_for_purge(cache_dir: &Path, wait: bool) -> io::Result<Result<PurgeLock, CacheBusy>> {
+    let file = match open_lock(&cache_dir.join(CACHE_LOCK_NAME))? {
+        Some(file) => {
+            if !try_lock(&file, wait)? {
+                return Ok(Err(CacheBusy::Rcargo));
+            }
+            Some(file)
+        }
+        None => None,
+    };
+
+    for cargo_lock in find_cargo_locks(cache_dir)? {
+        // Cargo's locks are only probed, never kept
+        let Some(cargo_lock_file) = open_lock(&cargo_lock)? else {
+            continue;
+        };
+        if !try_lock(&cargo_lock_file, wait)? {
+            return Ok(Err(CacheBusy::Cargo(cargo_lock)));
+        }
+    }
+
+    Ok(Ok(PurgeLock { _file: file }))
+}
+
+/// Opens a lock file, or returns `None` if there is none, e.g. because the
+/// build holding it just finished and removed its directory.
+fn open_lock(path: &Path) -> io::Result<Option<File>> {
+    match File::open(path) {
+        Ok(file) => Ok(Some(file)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Locks a file exclusively, returning false if it is busy and `wait` is not set.
+fn try_lock(file: &File, wait: bool) -> io::Result<bool> {
+    if wait {
+        file.lock()?;
+        return Ok(true);
+    }
+
+    match file.try_lock() {
+        Ok(()) => Ok(true),
+        Err(TryLockError::WouldBlock) => Ok(false),
+        Err(TryLockError::Error(e)) => Err(e),
+    }
+}
+
+/// Finds cargo's lock files in `<profile>/` and `<triple>/<profile>/`.
+fn find_cargo_locks(cache_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut locks = Vec::new();
+
+    for entry in fs::read_dir(cache_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+
+        let profile_lock = entry.path().join(CARGO_LOCK_NAME);
+        if profile_lock.is_file() {
+            locks.push(profile_lock);
+            continue;
+        }
+
+        for nested in fs::read_dir(entry.path())? {
+            let nested_lock = nested?.path().join(CARGO_LOCK_NAME);
+            if nested_lock.is_file() {
+                locks.push(nested_lock);
+            }
+        }
+    }
+
+    Ok(locks)
+}
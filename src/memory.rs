@@ -0,0 +1,278 @@
+use crate::plan::Redirect;
+use crate::space::{non_empty_var, use_fallback_root};
+use crate::utils::{filesystem_type, format_size, is_ram_backed_filesystem, parse_size};
+use std::fmt;
+use std::fs;
+use std::io;
+
+/// What to do when memory is too tight to build into a RAM-backed root.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LowMemoryPolicy {
+    /// Build anyway after printing a warning.
+    #[default]
+    Warn,
+    /// Build into `RCARGO_FALLBACK_TARGET_DIR` instead.
+    Fallback,
+    /// Refuse to build.
+    Abort,
+}
+
+impl LowMemoryPolicy {
+    /// Parses a policy name as given in `RCARGO_LOW_MEMORY_POLICY`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "warn" => Ok(LowMemoryPolicy::Warn),
+            "fallback" => Ok(LowMemoryPolicy::Fallback),
+            "abort" => Ok(LowMemoryPolicy::Abort),
+            _ => Err(format!(
+                "invalid low memory policy '{}', expected warn, fallback or abort",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LowMemoryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LowMemoryPolicy::Warn => write!(f, "warn"),
+            LowMemoryPolicy::Fallback => write!(f, "fallback"),
+            LowMemoryPolicy::Abort => write!(f, "abort"),
+        }
+    }
+}
+
+/// Gets the policy for memory pressure from `RCARGO_LOW_MEMORY_POLICY`.
+pub fn get_low_memory_policy() -> Result<LowMemoryPolicy, String> {
+    non_empty_var("RCARGO_LOW_MEMORY_POLICY")
+        .map(|value| LowMemoryPolicy::parse(&value))
+        .unwrap_or(Ok(LowMemoryPolicy::default()))
+}
+
+/// Gets the memory that must be available to build into RAM from
+/// `RCARGO_MIN_AVAILABLE_MEMORY`.
+pub fn get_min_available_memory() -> Result<Option<u64>, String> {
+    non_empty_var("RCARGO_MIN_AVAILABLE_MEMORY")
+        .map(|value| parse_size(&value))
+        .transpose()
+}
+
+/// Gets the swap usage above which builds stay out of RAM from
+/// `RCARGO_MAX_SWAP_USED`.
+pub fn get_max_swap_used() -> Result<Option<u64>, String> {
+    non_empty_var("RCARGO_MAX_SWAP_USED")
+        .map(|value| parse_size(&value))
+        .transpose()
+}
+
+/// Memory figures from `/proc/meminfo`, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryInfo {
+    /// Memory the kernel estimates is available without swapping.
+    pub available: u64,
+    pub swap_total: u64,
+    pub swap_free: u64,
+}
+
+impl MemoryInfo {
+    /// Reads `/proc/meminfo`; fails on systems without it.
+    pub fn read() -> io::Result<Self> {
+        Self::parse(&fs::read_to_string("/proc/meminfo")?)
+    }
+
+    /// Parses the `Name:   value kB` lines of `/proc/meminfo`.
+    fn parse(content: &str) -> io::Result<Self> {
+        let field = |name: &str| {
+            content.lines().find_map(|line| {
+                let value = line.strip_prefix(name)?.strip_prefix(':')?;
+                let kib: u64 = value.trim().trim_end_matches("kB").trim().parse().ok()?;
+                Some(kib * 1024)
+            })
+        };
+
+        let available = field("MemAvailable").ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "MemAvailable is missing from /proc/meminfo",
+            )
+        })?;
+        Ok(MemoryInfo {
+            available,
+            swap_total: field("SwapTotal").unwrap_or(0),
+            swap_free: field("SwapFree").unwrap_or(0),
+        })
+    }
+
+    pub fn swap_used(&self) -> u64 {
+        self.swap_total.saturating_sub(self.swap_free)
+    }
+}
+
+/// Describes the memory pressure exceeding the configured limits, if any.
+fn memory_pressure(
+    memory: &MemoryInfo,
+    min_available: Option<u64>,
+    max_swap_used: Option<u64>,
+) -> Option<String> {
+    if let Some(min_available) = min_available
+        && memory.available < min_available
+    {
+        return Some(format!(
+            "{} of memory available, below {}",
+            format_size(memory.available),
+            format_size(min_available)
+        ));
+    }
+    if let Some(max_swap_used) = max_swap_used
+        && memory.swap_used() > max_swap_used
+    {
+        return Some(format!(
+            "{} of swap in use, above {}",
+            format_size(memory.swap_used()),
+            format_size(max_swap_used)
+        ));
+    }
+    None
+}
+
+/// Checks that there is memory to spare for a build into a RAM-backed root
+/// and, if there is not, warns, switches to the fallback root or aborts,
+/// depending on `RCARGO_LOW_MEMORY_POLICY`.
+///
+/// On tmpfs the cache and the compiler compete for the same RAM, so a build
+/// into it while memory is short pushes the machine into swap.
+pub fn ensure_free_memory(redirect: &mut Redirect) -> Result<(), Box<dyn std::error::Error>> {
+    let policy = get_low_memory_policy()?;
+    let min_available = get_min_available_memory()?;
+    let max_swap_used = get_max_swap_used()?;
+    if min_available.is_none() && max_swap_used.is_none() {
+        return Ok(());
+    }
+
+    // Caches on disk only cost page cache, which the kernel reclaims freely
+    let target_root = redirect.build_root().path.clone();
+    let target_root = target_root.as_path();
+    fs::create_dir_all(target_root)?;
+    let in_ram =
+        filesystem_type(target_root).is_ok_and(|fs_type| is_ram_backed_filesystem(&fs_type));
+    if !in_ram {
+        return Ok(());
+    }
+
+    let Ok(memory) = MemoryInfo::read() else {
+        return Ok(());
+    };
+    let Some(pressure) = memory_pressure(&memory, min_available, max_swap_used) else {
+        return Ok(());
+    };
+
+    match policy {
+        LowMemoryPolicy::Warn => {
+            eprintln!(
+                "Warning: {}; building into RAM at '{}' may push the system into swap",
+                pressure,
+                target_root.display()
+            );
+        }
+        LowMemoryPolicy::Fallback => use_fallback_root(redirect, pressure)?,
+        LowMemoryPolicy::Abort => {
+            return Err(format!(
+                "{}; refusing to build into RAM at '{}', free some memory or change RCARGO_LOW_MEMORY_POLICY",
+                pressure,
+                target_root.display()
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIB: u64 = 1024 * 1024;
+
+    const MEMINFO: &str = "\
+MemTotal:       16303204 kB
+MemFree:         1210468 kB
+MemAvailable:    8388608 kB
+Buffers:          402596 kB
+SwapCached:        12288 kB
+SwapTotal:       4194304 kB
+SwapFree:        3145728 kB
+";
+
+    fn memory(available_mib: u64, swap_used_mib: u64) -> MemoryInfo {
+        MemoryInfo {
+            available: available_mib * MIB,
+            swap_total: 4096 * MIB,
+            swap_free: (4096 - swap_used_mib) * MIB,
+        }
+    }
+
+    #[test]
+    fn parses_meminfo() {
+        let memory = MemoryInfo::parse(MEMINFO).unwrap();
+        assert_eq!(memory.available, 8192 * MIB);
+        assert_eq!(memory.swap_total, 4096 * MIB);
+        assert_eq!(memory.swap_free, 3072 * MIB);
+        assert_eq!(memory.swap_used(), 1024 * MIB);
+    }
+
+    #[test]
+    fn does_not_mistake_swap_cached_for_swap_total() {
+        let content = "MemAvailable: 1024 kB\nSwapCached: 2048 kB\n";
+        let memory = MemoryInfo::parse(content).unwrap();
+        assert_eq!(memory.swap_total, 0);
+    }
+
+    #[test]
+    fn treats_missing_swap_as_none() {
+        let memory = MemoryInfo::parse("MemTotal: 2048 kB\nMemAvailable: 1024 kB\n").unwrap();
+        assert_eq!(memory.available, MIB);
+        assert_eq!(memory.swap_total, 0);
+        assert_eq!(memory.swap_used(), 0);
+    }
+
+    #[test]
+    fn fails_without_mem_available() {
+        let error = MemoryInfo::parse("MemTotal: 2048 kB\nSwapTotal: 0 kB\n").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn saturates_swap_used() {
+        let memory = MemoryInfo {
+            available: 0,
+            swap_total: MIB,
+            swap_free: 2 * MIB,
+        };
+        assert_eq!(memory.swap_used(), 0);
+    }
+
+    #[test]
+    fn no_pressure_without_limits() {
+        assert_eq!(memory_pressure(&memory(0, 4096), None, None), None);
+    }
+
+    #[test]
+    fn available_memory_at_the_limit_is_enough() {
+        let limit = Some(1024 * MIB);
+        assert_eq!(memory_pressure(&memory(1024, 0), limit, None), None);
+        assert!(memory_pressure(&memory(1023, 0), limit, None).is_some());
+    }
+
+    #[test]
+    fn swap_used_at_the_limit_is_allowed() {
+        let limit = Some(512 * MIB);
+        assert_eq!(memory_pressure(&memory(8192, 512), None, limit), None);
+        assert!(memory_pressure(&memory(8192, 513), None, limit).is_some());
+    }
+
+    #[test]
+    fn reports_low_memory_before_swap() {
+        let pressure = memory_pressure(&memory(100, 2048), Some(1024 * MIB), Some(MIB)).unwrap();
+        assert!(pressure.contains("of memory available"), "{}", pressure);
+    }
+}
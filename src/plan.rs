@@ -0,0 +1,144 @@
+use crate::demote::demotion;
+use crate::registry::Registry;
+use crate::roots::{ProfileSplit, RootSelection, TargetRoot, profile_root, select_root};
+use crate::utils::{
+    ArgsClassification, ResolvedProject, build_profile, build_targets, classify_cargo_args,
+    resolve_project,
+};
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Where a redirected cargo invocation puts its artifacts.
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    pub project: ResolvedProject,
+    /// Target root the cache lives in and the rule that picked it.
+    pub root: RootSelection,
+    /// Profile the root was selected for; routed profiles count as `dev`.
+    pub rule_profile: String,
+    pub cargo_target_dir: PathBuf,
+    /// Profile directories of this build linked in from another root.
+    pub split: Option<ProfileSplit>,
+    /// Slower root holding the cache since it was demoted for being idle; it
+    /// is moved back before the build.
+    pub demoted: Option<TargetRoot>,
+    /// Why the build was moved to the fallback root, shown in the status line.
+    pub fallback_reason: Option<String>,
+}
+
+/// Everything rcargo decides before running cargo.
+///
+/// Building a plan has no side effects, so `rcargo explain` can show exactly
+/// what a real invocation would do.
+#[derive(Debug, Clone)]
+pub struct ExecutionPlan {
+    pub cargo_path: String,
+    pub args: Vec<String>,
+    pub classification: ArgsClassification,
+    /// Whether redirection was skipped because the project ran `rcargo eject`.
+    pub opted_out: bool,
+    pub redirect: Option<Redirect>,
+}
+
+impl Redirect {
+    /// Root the build writes its artifacts to.
+    pub fn build_root(&self) -> &TargetRoot {
+        match &self.split {
+            Some(split) => &split.root,
+            None => &self.root.root,
+        }
+    }
+
+    /// Cache in the root the build writes its artifacts to.
+    pub fn build_cache_dir(&self) -> PathBuf {
+        match &self.split {
+            Some(split) => split.root.cache_dir(&self.project.identifier),
+            None => self.cargo_target_dir.clone(),
+        }
+    }
+}
+
+impl ExecutionPlan {
+    /// Classifies the arguments and, for build commands, resolves the project
+    /// and its cached target directory.
+    pub fn new(cargo_path: String, args: Vec<String>) -> Result<Self, Box<dyn std::error::Error>> {
+        let classification = classify_cargo_args(&args);
+
+        let mut opted_out = false;
+        let mut redirect = None;
+
+        if classification.requires_target_dir() {
+            let project = resolve_project(&cargo_path)?;
+            let registry = Registry::load_or_default();
+            opted_out = registry.is_opted_out(&project.identifier);
+
+            if !opted_out {
+                let record = registry.get(&project.identifier);
+                let profile = build_profile(&args);
+                // Routed profiles share the cache of dev builds and only
+                // move their own directories
+                let rule_profile = match profile_root(&profile)? {
+                    Some(_) => "dev",
+                    None => profile.as_str(),
+                };
+                let root = select_root(&project, rule_profile, record)?;
+                let cargo_target_dir = root.root.cache_dir(&project.identifier);
+                let split = ProfileSplit::new(&profile, &build_targets(&args), &root.root)?;
+                let is_demoted = record.is_some_and(|record| record.demoted);
+                let demoted = match demotion()? {
+                    Some(demotion) if is_demoted && root.root == *demotion.from => {
+                        Some(demotion.to.clone())
+                    }
+                    _ => None,
+                };
+
+                redirect = Some(Redirect {
+                    rule_profile: rule_profile.to_string(),
+                    project,
+                    root,
+                    cargo_target_dir,
+                    split,
+                    demoted,
+                    fallback_reason: None,
+                });
+            }
+        }
+
+        Ok(ExecutionPlan {
+            cargo_path,
+            args,
+            classification,
+            opted_out,
+            redirect,
+        })
+    }
+
+    /// Explains why cargo is or isn't redirected.
+    pub fn reason(&self) -> String {
+        if self.opted_out {
+            "project was ejected with `rcargo eject`".to_string()
+        } else {
+            self.classification.to_string()
+        }
+    }
+
+    /// Environment variables set for the cargo process.
+    pub fn env(&self) -> Vec<(&'static str, OsString)> {
+        match &self.redirect {
+            Some(redirect) => vec![(
+                "CARGO_TARGET_DIR",
+                redirect.cargo_target_dir.clone().into_os_string(),
+            )],
+            None => Vec::new(),
+        }
+    }
+
+    /// Builds the cargo command this plan executes.
+    pub fn command(&self) -> Command {
+        let mut cmd = Command::new(&self.cargo_path);
+        cmd.args(&self.args);
+        cmd.envs(self.env());
+        cmd
+    }
+}
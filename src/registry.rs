@@ -0,0 +1,190 @@
+use crate::get_target_dir;
+use crate::utils::{
+    ProjectIdentifier, TargetLinkState, get_target_link_name, inspect_target_symlink,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// File name of the registry inside the state directory.
+const REGISTRY_FILE_NAME: &str = "registry.json";
+
+/// File locked while the registry is being updated.
+const REGISTRY_LOCK_NAME: &str = "registry.lock";
+
+/// Persistent per-project state rcargo keeps across runs.
+///
+/// Unlike the caches themselves, the registry lives in a state directory on
+/// persistent storage, so it survives a RAM disk being wiped on reboot.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Registry {
+    #[serde(default)]
+    pub projects: BTreeMap<String, ProjectRecord>,
+    /// Unix timestamp of when a build last started looking for idle caches
+    /// to demote.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub demoter_started: Option<u64>,
+}
+
+/// What rcargo remembers about one project, keyed by its identifier.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ProjectRecord {
+    pub name: String,
+    pub path: PathBuf,
+    /// Unix timestamp of the last redirected build.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_used: Option<u64>,
+    /// Unix timestamp of when the access times of the cache were last reset
+    /// for `rcargo sweep`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_times_armed: Option<u64>,
+    /// Bytes the cache took up on disk after a successful build, measured at
+    /// most once a day.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_size: Option<u64>,
+    /// Unix timestamp of when `last_size` was measured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_size_measured: Option<u64>,
+    /// Set by `rcargo eject`; builds of the project are no longer redirected.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub opted_out: bool,
+    /// Set by `rcargo pin`; the cache is kept by purges and automatic cleanup.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub pinned: bool,
+    /// Set when the cache was moved to a slower root for being idle; the next
+    /// build moves it back.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub demoted: bool,
+    /// Root holding the cache for each profile the rules were matched with;
+    /// builds keep using it while the cache is there, even when the rules
+    /// would now pick another root.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub cache_roots: BTreeMap<String, String>,
+    /// Symlinks rcargo created in the project root, pointing at the cache.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub links: BTreeSet<PathBuf>,
+}
+
+impl ProjectRecord {
+    /// Tracks the project's target symlink if it currently points at the cache.
+    pub fn track_link(&mut self, project_path: &Path, cargo_target_dir: &Path) {
+        if inspect_target_symlink(project_path, cargo_target_dir) == TargetLinkState::Ok {
+            self.links.insert(project_path.join(get_target_link_name()));
+        }
+    }
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+/// Gets the directory holding rcargo's persistent state.
+///
+/// Uses `RCARGO_STATE_DIR`, then `$XDG_STATE_HOME/rcargo`, then
+/// `~/.local/state/rcargo`, and as a last resort a hidden directory in the
+/// target root.
+pub fn get_state_dir() -> PathBuf {
+    if let Some(dir) = env::var_os("RCARGO_STATE_DIR").filter(|dir| !dir.is_empty()) {
+        return PathBuf::from(dir);
+    }
+    if let Some(dir) = env::var_os("XDG_STATE_HOME").filter(|dir| !dir.is_empty()) {
+        return PathBuf::from(dir).join("rcargo");
+    }
+    if let Some(home) = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")) {
+        return PathBuf::from(home).join(".local/state/rcargo");
+    }
+    PathBuf::from(get_target_dir()).join(".rcargo-state")
+}
+
+pub const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Current time as a Unix timestamp in seconds.
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+impl Registry {
+    /// Loads the registry, returning an empty one if it does not exist yet.
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let path = get_state_dir().join(REGISTRY_FILE_NAME);
+        match fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content).map_err(|e| {
+                format!("Failed to parse registry '{}': {}", path.display(), e).into()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Registry::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Loads the registry on the build path, where an unreadable registry
+    /// must not fail the build; warns and starts from an empty one instead.
+    pub fn load_or_default() -> Self {
+        static WARNED: Once = Once::new();
+        Registry::load().unwrap_or_else(|e| {
+            WARNED.call_once(|| eprintln!("Warning: Could not load the rcargo registry: {}", e));
+            Registry::default()
+        })
+    }
+
+    /// Loads, modifies and saves the registry while holding its lock, so
+    /// concurrent rcargo processes do not lose each other's updates.
+    pub fn update<T>(f: impl FnOnce(&mut Registry) -> T) -> Result<T, Box<dyn std::error::Error>> {
+        let state_dir = get_state_dir();
+        fs::create_dir_all(&state_dir)?;
+
+        let lock = fs::File::create(state_dir.join(REGISTRY_LOCK_NAME))?;
+        lock.lock()?;
+
+        let mut registry = Registry::load()?;
+        let result = f(&mut registry);
+        registry.save(&state_dir)?;
+
+        Ok(result)
+    }
+
+    /// Writes the registry atomically by renaming a temporary file over it.
+    fn save(&self, state_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let path = state_dir.join(REGISTRY_FILE_NAME);
+        let tmp_path = state_dir.join(format!("{}.tmp", REGISTRY_FILE_NAME));
+
+        fs::write(&tmp_path, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    /// Gets the record of a project, if it has one.
+    pub fn get(&self, identifier: &ProjectIdentifier) -> Option<&ProjectRecord> {
+        self.projects.get(&identifier.identifier())
+    }
+
+    /// Gets the record of a project, creating it if needed.
+    pub fn record_mut(
+        &mut self,
+        identifier: &ProjectIdentifier,
+        project_path: &Path,
+    ) -> &mut ProjectRecord {
+        let record = self.projects.entry(identifier.identifier()).or_default();
+        record.name = identifier.name().to_string();
+        record.path = project_path.to_path_buf();
+        record
+    }
+
+    /// Whether the project was opted out of redirection with `rcargo eject`.
+    pub fn is_opted_out(&self, identifier: &ProjectIdentifier) -> bool {
+        self.get(identifier).is_some_and(|record| record.opted_out)
+    }
+
+    /// Whether the cache with this identifier was pinned with `rcargo pin`.
+    pub fn is_pinned(&self, identifier: &str) -> bool {
+        self.projects
+            .get(identifier)
+            .is_some_and(|record| record.pinned)
+    }
+}
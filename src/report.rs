@@ -0,0 +1,66 @@
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// Version of the JSON and CSV output schemas, bumped on incompatible changes.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// How command results are printed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Table,
+    /// A JSON document
+    Json,
+    /// Comma-separated values with a header row
+    Csv,
+}
+
+impl OutputFormat {
+    /// Whether the output is meant for people rather than scripts.
+    pub fn is_human(self) -> bool {
+        self == OutputFormat::Table
+    }
+
+    /// Gets the machine-readable format, `None` for a table.
+    pub fn machine(self) -> Option<MachineFormat> {
+        match self {
+            OutputFormat::Table => None,
+            OutputFormat::Json => Some(MachineFormat::Json),
+            OutputFormat::Csv => Some(MachineFormat::Csv),
+        }
+    }
+}
+
+/// Output formats meant for scripts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MachineFormat {
+    Json,
+    Csv,
+}
+
+/// Quotes a CSV field when it contains separators, quotes or line breaks.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Prints CSV rows under a header, each row starting with the schema version.
+pub fn print_csv(header: &[&str], rows: &[Vec<String>]) {
+    println!("schema_version,{}", header.join(","));
+    for row in rows {
+        let fields: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+        println!("{},{}", SCHEMA_VERSION, fields.join(","));
+    }
+}
+
+/// Renders a unit enum variant the way serde names it, e.g. `would_purge`.
+pub fn serde_plain(value: &impl Serialize) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
@@ -0,0 +1,694 @@
+use crate::DEFAULT_TARGET_DIR;
+use crate::config::{ConfigFile, config_file};
+use crate::registry::ProjectRecord;
+use crate::space::get_fallback_target_dir;
+use crate::utils::{
+    ProjectIdentifier, ResolvedProject, glob_match, list_project_caches, move_directory,
+    parse_size, profile_dir_name, symlink_dir,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Name of the only root when no roots are configured.
+pub const DEFAULT_ROOT_NAME: &str = "default";
+
+/// Name of `RCARGO_FALLBACK_TARGET_DIR` among the roots.
+pub const FALLBACK_ROOT_NAME: &str = "fallback";
+
+static TARGET_ROOTS: OnceLock<Result<Vec<TargetRoot>, String>> = OnceLock::new();
+
+/// A named directory holding project caches, such as a RAM disk or an SSD.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TargetRoot {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl TargetRoot {
+    /// Gets the cache directory of a project in this root.
+    pub fn cache_dir(&self, identifier: &ProjectIdentifier) -> PathBuf {
+        self.path.join(identifier.identifier())
+    }
+}
+
+impl fmt::Display for TargetRoot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.name, self.path.display())
+    }
+}
+
+/// Checks the configured roots and the rules and profile routes referring to
+/// them.
+fn validate_roots(config: &ConfigFile) -> Result<(), String> {
+    let roots = &config.roots;
+    for (index, root) in roots.iter().enumerate() {
+        if root.name.is_empty() || root.path.as_os_str().is_empty() {
+            return Err("Target roots need a name and a path".to_string());
+        }
+        if roots[..index].iter().any(|other| other.name == root.name) {
+            return Err(format!("Target root '{}' is configured twice", root.name));
+        }
+    }
+
+    for (index, rule) in config.rules.iter().enumerate() {
+        if !roots.iter().any(|root| root.name == rule.root) {
+            return Err(format!(
+                "Rule {} refers to unknown target root '{}'",
+                index + 1,
+                rule.root
+            ));
+        }
+    }
+
+    for (profile, root_name) in &config.profile_roots {
+        if !roots.iter().any(|root| root.name == *root_name) {
+            return Err(format!(
+                "Profile '{}' is routed to unknown target root '{}'",
+                profile, root_name
+            ));
+        }
+    }
+
+    if let Some(demote) = &config.demote {
+        for root_name in demote.from.iter().chain([&demote.to]) {
+            if !roots.iter().any(|root| root.name == *root_name) {
+                return Err(format!(
+                    "Caches are demoted to or from unknown target root '{}'",
+                    root_name
+                ));
+            }
+        }
+        let from = demote
+            .from
+            .as_ref()
+            .or(roots.first().map(|root| &root.name));
+        if from == Some(&demote.to) {
+            return Err(format!(
+                "Caches are demoted from '{}' to the same root",
+                demote.to
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Gets the target roots, the preferred one first.
+///
+/// `RCARGO_TARGET_DIR` replaces the configured roots with a single one, and
+/// without either the default location is used. The fallback root of the low
+/// space and memory policies comes last.
+pub fn target_roots() -> Result<&'static [TargetRoot], String> {
+    TARGET_ROOTS
+        .get_or_init(|| {
+            let mut roots = match env::var("RCARGO_TARGET_DIR") {
+                Ok(path) => vec![TargetRoot {
+                    name: DEFAULT_ROOT_NAME.to_string(),
+                    path: PathBuf::from(path),
+                }],
+                Err(_) => {
+                    let config = config_file()?;
+                    validate_roots(config)?;
+                    config.roots.clone()
+                }
+            };
+            if roots.is_empty() {
+                roots.push(TargetRoot {
+                    name: DEFAULT_ROOT_NAME.to_string(),
+                    path: PathBuf::from(DEFAULT_TARGET_DIR),
+                });
+            }
+
+            if let Some(path) = get_fallback_target_dir()
+                && !roots.iter().any(|root| root.path == path)
+            {
+                roots.push(TargetRoot {
+                    name: FALLBACK_ROOT_NAME.to_string(),
+                    path,
+                });
+            }
+            Ok(roots)
+        })
+        .as_ref()
+        .map(Vec::as_slice)
+        .map_err(Clone::clone)
+}
+
+/// Whether cache listings need to say which root a cache is in.
+fn has_multiple_roots() -> bool {
+    target_roots().is_ok_and(|roots| roots.len() > 1)
+}
+
+/// Parses an optional size such as `5GiB` from the configuration file.
+fn deserialize_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| parse_size(&value).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Sends the builds it matches to a target root.
+///
+/// Every condition given must match; a rule without conditions matches all
+/// builds.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RootRule {
+    /// Name of the root matching builds go to.
+    pub root: String,
+    /// Glob matched against the project path; `~/` stands for the home directory.
+    pub path: Option<String>,
+    /// Glob matched against the project name.
+    pub name: Option<String>,
+    /// Cargo profile of the build, e.g. `dev`, `release` or `bench`.
+    pub profile: Option<String>,
+    /// Matches projects whose cache last took up at least this much.
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub min_size: Option<u64>,
+    /// Matches projects whose cache last took up at most this much, or whose
+    /// size is not known yet.
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub max_size: Option<u64>,
+}
+
+impl RootRule {
+    /// Checks whether a build of a project with a profile matches the rule.
+    fn matches(&self, project: &ResolvedProject, profile: &str, last_size: Option<u64>) -> bool {
+        let path_matches = self.path.as_ref().is_none_or(|pattern| {
+            let pattern = match (pattern.strip_prefix("~/"), env::var_os("HOME")) {
+                (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+                _ => PathBuf::from(pattern),
+            };
+            glob_match(&pattern.to_string_lossy(), &project.path.to_string_lossy())
+        });
+        let name_matches = self
+            .name
+            .as_ref()
+            .is_none_or(|pattern| glob_match(pattern, project.identifier.name()));
+        let profile_matches = self.profile.as_ref().is_none_or(|rule| rule == profile);
+        let min_size_matches = self
+            .min_size
+            .is_none_or(|min_size| last_size.is_some_and(|size| size >= min_size));
+        let max_size_matches = self
+            .max_size
+            .is_none_or(|max_size| last_size.is_none_or(|size| size <= max_size));
+
+        path_matches && name_matches && profile_matches && min_size_matches && max_size_matches
+    }
+}
+
+/// The root a build goes to and why.
+#[derive(Debug, Clone)]
+pub struct RootSelection {
+    pub root: TargetRoot,
+    /// One-based number of the rule that chose the root, `None` for the
+    /// preferred root.
+    pub rule: Option<usize>,
+    /// Whether the root was kept because the cache is already there, while
+    /// the rules now pick another one.
+    pub kept: bool,
+}
+
+impl RootSelection {
+    /// Selects a root without consulting rules or existing caches.
+    pub fn fixed(root: TargetRoot) -> Self {
+        RootSelection {
+            root,
+            rule: None,
+            kept: false,
+        }
+    }
+}
+
+impl fmt::Display for RootSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.rule {
+            _ if self.kept => write!(f, "{}, kept where the cache is", self.root),
+            Some(rule) => write!(f, "{}, rule {}", self.root, rule),
+            None => write!(f, "{}", self.root),
+        }
+    }
+}
+
+/// Picks the root for a build from the first matching rule, defaulting to the
+/// preferred root.
+///
+/// A project whose cache for the profile is already in another root keeps
+/// building there, so crossing a size threshold or editing the rules does not
+/// start a cold cache and orphan the old one. A demoted cache counts as being
+/// in the root it was demoted from.
+pub fn select_root(
+    project: &ResolvedProject,
+    profile: &str,
+    record: Option<&ProjectRecord>,
+) -> Result<RootSelection, String> {
+    let roots = target_roots()?;
+
+    // Rules refer to configured roots, which `RCARGO_TARGET_DIR` replaces
+    if env::var_os("RCARGO_TARGET_DIR").is_some() {
+        return Ok(RootSelection::fixed(roots[0].clone()));
+    }
+
+    let last_size = record.and_then(|record| record.last_size);
+    let rules = &config_file()?.rules;
+    let matching = rules
+        .iter()
+        .enumerate()
+        .find(|(_, rule)| rule.matches(project, profile, last_size));
+    let selection = match matching {
+        Some((index, rule)) => RootSelection {
+            root: roots
+                .iter()
+                .find(|root| root.name == rule.root)
+                .cloned()
+                .ok_or_else(|| format!("Unknown target root '{}'", rule.root))?,
+            rule: Some(index + 1),
+            kept: false,
+        },
+        None => RootSelection::fixed(roots[0].clone()),
+    };
+
+    let cache_root = record
+        .and_then(|record| record.cache_roots.get(profile))
+        .filter(|name| **name != selection.root.name)
+        .and_then(|name| roots.iter().find(|root| root.name == *name));
+    if let Some(cache_root) = cache_root
+        && (cache_root.cache_dir(&project.identifier).is_dir()
+            || record.is_some_and(|record| record.demoted))
+    {
+        return Ok(RootSelection {
+            root: cache_root.clone(),
+            rule: None,
+            kept: true,
+        });
+    }
+    Ok(selection)
+}
+
+/// A project cache in one of the target roots.
+#[derive(Debug, Clone)]
+pub struct CacheDir {
+    pub root: TargetRoot,
+    pub identifier: String,
+    pub path: PathBuf,
+}
+
+impl fmt::Display for CacheDir {
+    /// Shows the identifier, prefixed with the root's name when there are
+    /// several roots.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if has_multiple_roots() {
+            write!(f, "{}:{}", self.root.name, self.identifier)
+        } else {
+            write!(f, "{}", self.identifier)
+        }
+    }
+}
+
+/// Lists the project caches in every target root, skipping roots that do not
+/// exist yet.
+pub fn list_caches() -> Result<Vec<CacheDir>, Box<dyn std::error::Error>> {
+    let mut caches = Vec::new();
+
+    for root in target_roots()? {
+        if !root.path.is_dir() {
+            continue;
+        }
+        for path in list_project_caches(&root.path)? {
+            caches.push(CacheDir {
+                root: root.clone(),
+                identifier: path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .to_string(),
+                path,
+            });
+        }
+    }
+
+    Ok(caches)
+}
+
+/// Lists the caches a project has across the target roots.
+pub fn project_caches(
+    identifier: &ProjectIdentifier,
+) -> Result<Vec<CacheDir>, Box<dyn std::error::Error>> {
+    let mut caches = Vec::new();
+
+    for root in target_roots()? {
+        let path = root.cache_dir(identifier);
+        if path.is_dir() {
+            caches.push(CacheDir {
+                root: root.clone(),
+                identifier: identifier.identifier(),
+                path,
+            });
+        }
+    }
+
+    Ok(caches)
+}
+
+/// Finds the root a profile is routed to in `[profile_roots]`, by its name or
+/// by the directory cargo builds it into, so `bench` follows `release`.
+///
+/// `RCARGO_TARGET_DIR` disables the routes along with the configured roots.
+pub fn profile_root(profile: &str) -> Result<Option<&'static TargetRoot>, String> {
+    if env::var_os("RCARGO_TARGET_DIR").is_some() {
+        return Ok(None);
+    }
+
+    let routes = &config_file()?.profile_roots;
+    let root_name = routes.get(profile).or_else(|| {
+        routes
+            .iter()
+            .find(|(routed, _)| profile_dir_name(routed) == profile_dir_name(profile))
+            .map(|(_, root_name)| root_name)
+    });
+    match root_name {
+        Some(root_name) => target_roots()?
+            .iter()
+            .find(|root| root.name == *root_name)
+            .map(Some)
+            .ok_or_else(|| format!("Unknown target root '{}'", root_name)),
+        None => Ok(None),
+    }
+}
+
+/// Profile directories kept in another root than the rest of a cache, and
+/// linked into it so cargo still finds them under `CARGO_TARGET_DIR`.
+#[derive(Debug, Clone)]
+pub struct ProfileSplit {
+    pub root: TargetRoot,
+    /// Directories relative to the cache, e.g. `release` or
+    /// `aarch64-unknown-linux-gnu/release` for cross builds.
+    pub profile_dirs: Vec<PathBuf>,
+}
+
+impl ProfileSplit {
+    /// Routes the output of a build with a profile and `--target` triples,
+    /// unless its profile stays in the root of the rest of the cache.
+    pub fn new(
+        profile: &str,
+        triples: &[String],
+        cache_root: &TargetRoot,
+    ) -> Result<Option<Self>, String> {
+        let Some(root) = profile_root(profile)? else {
+            return Ok(None);
+        };
+        if root == cache_root {
+            return Ok(None);
+        }
+
+        let dir_name = profile_dir_name(profile);
+        let profile_dirs = if triples.is_empty() {
+            vec![PathBuf::from(dir_name)]
+        } else {
+            triples
+                .iter()
+                .map(|triple| Path::new(triple).join(dir_name))
+                .collect()
+        };
+        Ok(Some(ProfileSplit {
+            root: root.clone(),
+            profile_dirs,
+        }))
+    }
+
+    /// Links the profile directories of a cache to their root, moving over
+    /// any that were built before the profile was routed.
+    pub fn link(&self, cache_dir: &Path, identifier: &ProjectIdentifier) -> io::Result<()> {
+        let split_dir = self.root.cache_dir(identifier);
+        for profile_dir in &self.profile_dirs {
+            link_profile_dir(&cache_dir.join(profile_dir), &split_dir.join(profile_dir))?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ProfileSplit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let profile_dirs: Vec<String> = self
+            .profile_dirs
+            .iter()
+            .map(|profile_dir| profile_dir.display().to_string())
+            .collect();
+        write!(f, "{} in {}", profile_dirs.join(", "), self.root)
+    }
+}
+
+/// Finds the profile directories of a target directory routed to another
+/// root than `cache_root`, including those of cross builds.
+pub fn routed_profile_dirs(
+    target_dir: &Path,
+    cache_root: &TargetRoot,
+) -> Result<Vec<ProfileSplit>, Box<dyn std::error::Error>> {
+    let mut splits = Vec::new();
+    for entry in fs::read_dir(target_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if let Some(split) = ProfileSplit::new(&name, &[], cache_root)? {
+            splits.push(split);
+            continue;
+        }
+
+        // Profile directories of cross builds sit under the target triple
+        for nested in fs::read_dir(entry.path())? {
+            let nested = nested?;
+            if !nested.file_type()?.is_dir() {
+                continue;
+            }
+            let profile = nested.file_name().to_string_lossy().into_owned();
+            if let Some(split) =
+                ProfileSplit::new(&profile, std::slice::from_ref(&name), cache_root)?
+            {
+                splits.push(split);
+            }
+        }
+    }
+    Ok(splits)
+}
+
+/// Moves a profile directory to `target` and leaves a symlink in its place;
+/// a link already pointing there is kept.
+///
+/// A directory present on both sides is left alone with a warning rather than
+/// merged, and the build then uses the one in the cache.
+pub fn link_profile_dir(link: &Path, target: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(link) {
+        Ok(metadata) if metadata.is_symlink() => {
+            if fs::read_link(link)? == target {
+                // Recreated if purged since
+                return fs::create_dir_all(target);
+            }
+            fs::remove_file(link)?;
+        }
+        Ok(metadata) if metadata.is_dir() => {
+            if target.is_dir() && fs::read_dir(target)?.next().is_some() {
+                eprintln!(
+                    "Warning: Both '{}' and '{}' exist; keeping the profile in the cache, purge one of them to route it",
+                    link.display(),
+                    target.display()
+                );
+                return Ok(());
+            }
+            if target.is_dir() {
+                fs::remove_dir(target)?;
+            }
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            move_directory(link, target)?;
+        }
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("'{}' is not a directory", link.display()),
+            ));
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    fs::create_dir_all(target)?;
+    if let Some(parent) = link.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    symlink_dir(target, link)
+}
+
+/// A profile directory of a cache linked to another root.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct LinkedProfile {
+    /// Path relative to the cache, e.g. `release`.
+    pub profile_dir: PathBuf,
+    pub target: PathBuf,
+}
+
+/// Lists the profile directories of a cache that are symlinks into other
+/// roots.
+pub fn linked_profile_dirs(cache_dir: &Path) -> io::Result<Vec<LinkedProfile>> {
+    let mut linked = Vec::new();
+    let mut check = |relative: PathBuf, path: &Path| -> io::Result<()> {
+        if fs::symlink_metadata(path)?.is_symlink() {
+            let target = fs::read_link(path)?;
+            let in_root = target_roots()
+                .is_ok_and(|roots| roots.iter().any(|root| target.starts_with(&root.path)));
+            if in_root {
+                linked.push(LinkedProfile {
+                    profile_dir: relative,
+                    target,
+                });
+            }
+        }
+        Ok(())
+    };
+
+    for entry in fs::read_dir(cache_dir)? {
+        let entry = entry?;
+        let name = PathBuf::from(entry.file_name());
+        check(name.clone(), &entry.path())?;
+
+        // Profile directories of cross builds, one level down
+        if entry.file_type()?.is_dir() {
+            for nested in fs::read_dir(entry.path())? {
+                let nested = nested?;
+                check(name.join(nested.file_name()), &nested.path())?;
+            }
+        }
+    }
+
+    linked.sort();
+    Ok(linked)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    fn project(path: &str, identifier: &str) -> ResolvedProject {
+        ResolvedProject {
+            path: PathBuf::from(path),
+            from_cargo_metadata: true,
+            identifier: ProjectIdentifier::from_identifier(identifier).unwrap(),
+        }
+    }
+
+    fn rule() -> RootRule {
+        RootRule {
+            root: "nvme".to_string(),
+            path: None,
+            name: None,
+            profile: None,
+            min_size: None,
+            max_size: None,
+        }
+    }
+
+    #[test]
+    fn rule_without_conditions_matches_everything() {
+        let app = project("/work/app", "app-1234567");
+        assert!(rule().matches(&app, "dev", None));
+        assert!(rule().matches(&app, "release", Some(GIB)));
+    }
+
+    #[test]
+    fn rule_matches_path_and_name_globs() {
+        let app = project("/work/big/app", "app-1234567");
+        let by_path = RootRule {
+            path: Some("/work/big/*".to_string()),
+            ..rule()
+        };
+        assert!(by_path.matches(&app, "dev", None));
+        assert!(!by_path.matches(&project("/work/small/app", "app-1234567"), "dev", None));
+
+        let by_name = RootRule {
+            name: Some("ap?".to_string()),
+            ..rule()
+        };
+        assert!(by_name.matches(&app, "dev", None));
+        assert!(!by_name.matches(&project("/work/big/tool", "tool-1234567"), "dev", None));
+    }
+
+    #[test]
+    fn rule_expands_home_in_paths() {
+        let Some(home) = env::var_os("HOME") else {
+            return;
+        };
+        let app_path = PathBuf::from(home).join("work/app");
+        let app = project(&app_path.to_string_lossy(), "app-1234567");
+        let by_path = RootRule {
+            path: Some("~/work/*".to_string()),
+            ..rule()
+        };
+        assert!(by_path.matches(&app, "dev", None));
+    }
+
+    #[test]
+    fn rule_matches_profile_exactly() {
+        let app = project("/work/app", "app-1234567");
+        let release = RootRule {
+            profile: Some("release".to_string()),
+            ..rule()
+        };
+        assert!(release.matches(&app, "release", None));
+        assert!(!release.matches(&app, "dev", None));
+        assert!(!release.matches(&app, "bench", None));
+    }
+
+    #[test]
+    fn rule_size_limits_are_inclusive() {
+        let app = project("/work/app", "app-1234567");
+        let big = RootRule {
+            min_size: Some(8 * GIB),
+            ..rule()
+        };
+        assert!(big.matches(&app, "dev", Some(8 * GIB)));
+        assert!(!big.matches(&app, "dev", Some(8 * GIB - 1)));
+
+        let small = RootRule {
+            max_size: Some(GIB),
+            ..rule()
+        };
+        assert!(small.matches(&app, "dev", Some(GIB)));
+        assert!(!small.matches(&app, "dev", Some(GIB + 1)));
+    }
+
+    #[test]
+    fn unknown_size_only_matches_max_size() {
+        let app = project("/work/app", "app-1234567");
+        let big = RootRule {
+            min_size: Some(GIB),
+            ..rule()
+        };
+        let small = RootRule {
+            max_size: Some(GIB),
+            ..rule()
+        };
+        assert!(!big.matches(&app, "dev", None));
+        assert!(small.matches(&app, "dev", None));
+    }
+
+    #[test]
+    fn rule_needs_every_condition() {
+        let app = project("/work/app", "app-1234567");
+        let combined = RootRule {
+            name: Some("app".to_string()),
+            profile: Some("release".to_string()),
+            ..rule()
+        };
+        assert!(combined.matches(&app, "release", None));
+        assert!(!combined.matches(&app, "dev", None));
+        assert!(!combined.matches(&project("/work/other", "other-1234567"), "release", None));
+    }
+}
@@ -0,0 +1,252 @@
+use crate::commands::links::settle_project_links;
+use crate::lock::lock_for_purge;
+use crate::plan::Redirect;
+use crate::registry::Registry;
+use crate::roots::{RootSelection, target_roots};
+use crate::trash::PurgeTrash;
+use crate::utils::{
+    filesystem_space, format_size, list_project_caches, measure_directory, parse_size,
+};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What to do when the target root has less free space than a build needs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LowSpacePolicy {
+    /// Build anyway after printing a warning.
+    #[default]
+    Warn,
+    /// Delete the least recently used caches until there is enough space.
+    Evict,
+    /// Build into `RCARGO_FALLBACK_TARGET_DIR` instead.
+    Fallback,
+    /// Refuse to build.
+    Abort,
+}
+
+impl LowSpacePolicy {
+    /// Parses a policy name as given in `RCARGO_LOW_SPACE_POLICY`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.to_ascii_lowercase().as_str() {
+            "warn" => Ok(LowSpacePolicy::Warn),
+            "evict" => Ok(LowSpacePolicy::Evict),
+            "fallback" => Ok(LowSpacePolicy::Fallback),
+            "abort" => Ok(LowSpacePolicy::Abort),
+            _ => Err(format!(
+                "invalid low space policy '{}', expected warn, evict, fallback or abort",
+                value
+            )),
+        }
+    }
+}
+
+impl fmt::Display for LowSpacePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LowSpacePolicy::Warn => write!(f, "warn"),
+            LowSpacePolicy::Evict => write!(f, "evict"),
+            LowSpacePolicy::Fallback => write!(f, "fallback"),
+            LowSpacePolicy::Abort => write!(f, "abort"),
+        }
+    }
+}
+
+/// Reads a variable, treating an empty value as unset.
+pub fn non_empty_var(variable: &str) -> Option<String> {
+    env::var(variable).ok().filter(|value| !value.is_empty())
+}
+
+/// Gets the policy for low free space from `RCARGO_LOW_SPACE_POLICY`.
+pub fn get_low_space_policy() -> Result<LowSpacePolicy, String> {
+    non_empty_var("RCARGO_LOW_SPACE_POLICY")
+        .map(|value| LowSpacePolicy::parse(&value))
+        .unwrap_or(Ok(LowSpacePolicy::default()))
+}
+
+/// Gets the free space every build needs from `RCARGO_MIN_FREE_SPACE`.
+pub fn get_min_free_space() -> Result<Option<u64>, String> {
+    non_empty_var("RCARGO_MIN_FREE_SPACE")
+        .map(|value| parse_size(&value))
+        .transpose()
+}
+
+/// Gets the target root used by the `fallback` policies from
+/// `RCARGO_FALLBACK_TARGET_DIR`.
+pub fn get_fallback_target_dir() -> Option<PathBuf> {
+    non_empty_var("RCARGO_FALLBACK_TARGET_DIR").map(PathBuf::from)
+}
+
+/// Moves a build to the fallback root, failing if none is configured.
+pub fn use_fallback_root(
+    redirect: &mut Redirect,
+    reason: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let fallback_root = get_fallback_target_dir().ok_or_else(|| {
+        format!(
+            "RCARGO_FALLBACK_TARGET_DIR is not set for the fallback policy ({})",
+            reason
+        )
+    })?;
+    let root = target_roots()?
+        .iter()
+        .find(|root| root.path == fallback_root)
+        .cloned()
+        .ok_or("The fallback root is missing from the target roots")?;
+    redirect.cargo_target_dir = root.cache_dir(&redirect.project.identifier);
+    redirect.root = RootSelection::fixed(root);
+    // The whole build moves, including routed profiles
+    redirect.split = None;
+    redirect.fallback_reason = Some(reason);
+    Ok(())
+}
+
+/// Estimates the free space a build needs before it starts.
+///
+/// A project whose cache is gone, e.g. after a purge or a reboot, is rebuilt
+/// from scratch and needs about as much as its cache last took up. Builds into
+/// an existing cache only grow it, so they need just `RCARGO_MIN_FREE_SPACE`.
+fn required_space(cache_dir: &Path, last_size: Option<u64>, min_free_space: Option<u64>) -> u64 {
+    let rebuild = if cache_dir.exists() {
+        0
+    } else {
+        last_size.unwrap_or(0)
+    };
+    rebuild.max(min_free_space.unwrap_or(0))
+}
+
+/// Moves the least recently used caches in a target root to the trash until
+/// `required` bytes are free, sparing the cache being built, pinned caches and
+/// caches in use. Returns the free space afterwards.
+///
+/// The trash is deleted right away in the background, so a trashed cache is
+/// counted as free space the build can expect, not space freed already.
+fn evict_caches(
+    target_root: &Path,
+    keep: &Path,
+    required: u64,
+    mut available: u64,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let registry = Registry::load_or_default();
+    let mut candidates: Vec<(Option<u64>, String, PathBuf)> = Vec::new();
+
+    for cache_dir in list_project_caches(target_root)? {
+        let identifier = cache_dir
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        if cache_dir == keep || registry.is_pinned(&identifier) {
+            continue;
+        }
+        let last_used = registry
+            .projects
+            .get(&identifier)
+            .and_then(|record| record.last_used);
+        candidates.push((last_used, identifier, cache_dir));
+    }
+    // Caches rcargo never built count as the oldest
+    candidates.sort();
+
+    let mut trash = PurgeTrash::new();
+    let mut evicted = Vec::new();
+    for (_, identifier, cache_dir) in candidates {
+        if available >= required {
+            break;
+        }
+        let Ok(_lock) = lock_for_purge(&cache_dir, false)? else {
+            continue;
+        };
+
+        let size = measure_directory(&cache_dir)?.allocated_bytes;
+        if trash.add(target_root, &cache_dir)? {
+            eprintln!(
+                "RCargo: Moved '{}' ({}) to the trash to make room for the build",
+                identifier,
+                format_size(size)
+            );
+        } else {
+            fs::remove_dir_all(&cache_dir)?;
+            eprintln!(
+                "RCargo: Deleted '{}' ({}) to make room for the build",
+                identifier,
+                format_size(size)
+            );
+        }
+        available = available.saturating_add(size);
+        evicted.push(identifier);
+    }
+    trash.finish_now()?;
+
+    if !evicted.is_empty() {
+        for settled_link in settle_project_links(Some(&evicted))? {
+            eprintln!("{}", settled_link);
+        }
+    }
+    Ok(available)
+}
+
+/// Checks that the target root has room for a redirected build and, if it
+/// does not, evicts caches, switches to the fallback root, aborts or warns,
+/// depending on `RCARGO_LOW_SPACE_POLICY`.
+pub fn ensure_free_space(redirect: &mut Redirect) -> Result<(), Box<dyn std::error::Error>> {
+    let policy = get_low_space_policy()?;
+    let target_root = redirect.build_root().path.clone();
+    let target_root = target_root.as_path();
+    let last_size = Registry::load_or_default()
+        .get(&redirect.project.identifier)
+        .and_then(|record| record.last_size);
+    let build_cache_dir = redirect.build_cache_dir();
+    let required = required_space(&build_cache_dir, last_size, get_min_free_space()?);
+    if required == 0 {
+        return Ok(());
+    }
+
+    // The root is created here, so a fresh one is checked as well
+    fs::create_dir_all(target_root)?;
+    let Ok(space) = filesystem_space(target_root) else {
+        return Ok(());
+    };
+    if space.available >= required {
+        return Ok(());
+    }
+
+    let shortage = format!(
+        "Only {} free in '{}', but the build needs about {}",
+        format_size(space.available),
+        target_root.display(),
+        format_size(required)
+    );
+    match policy {
+        LowSpacePolicy::Warn => {
+            eprintln!("Warning: {}; the build may run out of space", shortage);
+        }
+        LowSpacePolicy::Evict => {
+            let available = evict_caches(target_root, &build_cache_dir, required, space.available)?;
+            if available < required {
+                eprintln!(
+                    "Warning: Only {} free in '{}' after evicting every unpinned cache not in use; the build may run out of space",
+                    format_size(available),
+                    target_root.display()
+                );
+            }
+        }
+        LowSpacePolicy::Fallback => {
+            let reason = format!(
+                "only {} free in '{}'",
+                format_size(space.available),
+                target_root.display()
+            );
+            use_fallback_root(redirect, reason)?;
+        }
+        LowSpacePolicy::Abort => {
+            return Err(format!(
+                "{}; free space with `rcargo purge` or `rcargo sweep`, or change RCARGO_LOW_SPACE_POLICY",
+                shortage
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
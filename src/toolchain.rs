@@ -0,0 +1,89 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// How much of a metadata file is searched for the rustc version string.
+///
+/// The version sits in the `.rmeta` header, which is also the first member of
+/// an `.rlib` archive.
+const METADATA_HEADER_LIMIT: u64 = 8 * 1024;
+
+/// Reads the version of the rustc that produced an `.rmeta` or `.rlib` file,
+/// e.g. `rustc 1.95.0 (59807616e 2026-04-14)`.
+pub fn read_metadata_rustc_version(path: &Path) -> io::Result<Option<String>> {
+    let mut header = Vec::new();
+    File::open(path)?
+        .take(METADATA_HEADER_LIMIT)
+        .read_to_end(&mut header)?;
+
+    let Some(start) = header
+        .windows(b"rustc ".len())
+        .position(|window| window == b"rustc ")
+    else {
+        return Ok(None);
+    };
+    let Some(length) = header[start..].iter().position(|&byte| byte == b')') else {
+        return Ok(None);
+    };
+
+    Ok(String::from_utf8(header[start..=start + length].to_vec()).ok())
+}
+
+/// Runs `rustc -vV` and returns its first line, the same version string
+/// rustc writes into crate metadata.
+fn rustc_version(rustc: &Path) -> Option<String> {
+    let output = Command::new(rustc).arg("-vV").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .map(|line| line.trim().to_string())
+}
+
+/// Lists the installation directories of the toolchains rustup knows about.
+fn rustup_toolchain_dirs() -> Vec<PathBuf> {
+    let Ok(output) = Command::new("rustup")
+        .args(["toolchain", "list", "-v"])
+        .output()
+    else {
+        return Vec::new();
+    };
+    if !output.status.success() {
+        return Vec::new();
+    }
+
+    // Lines look like `stable-x86_64-unknown-linux-gnu (active, default) /path`
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_whitespace().last())
+        .map(PathBuf::from)
+        .filter(|path| path.is_dir())
+        .collect()
+}
+
+/// Collects the versions of every installed rustc.
+///
+/// Covers each rustup toolchain, plus the rustc next to the discovered cargo
+/// (or on `PATH`) for installations not managed by rustup.
+pub fn installed_rustc_versions(cargo_path: &str) -> BTreeSet<String> {
+    let mut rustc_paths: Vec<PathBuf> = rustup_toolchain_dirs()
+        .into_iter()
+        .map(|toolchain| toolchain.join("bin").join("rustc"))
+        .collect();
+
+    let sibling_rustc = Path::new(cargo_path)
+        .parent()
+        .map(|bin_dir| bin_dir.join("rustc"))
+        .filter(|rustc| rustc.is_file());
+    rustc_paths.push(sibling_rustc.unwrap_or_else(|| PathBuf::from("rustc")));
+
+    rustc_paths
+        .iter()
+        .filter_map(|rustc| rustc_version(rustc))
+        .collect()
+}
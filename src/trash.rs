@@ -0,0 +1,513 @@
+use crate::commands::links::SettledLink;
+use crate::registry::{Registry, unix_now};
+use crate::roots::target_roots;
+use crate::utils::{filesystem_space, spawn_detached, symlink_dir};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use std::fs::{self, File, TryLockError};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Directory under a target root holding purged caches until they are deleted.
+pub const TRASH_DIR_NAME: &str = ".trash";
+
+/// Lists the purged paths of a batch; deleted first, so a batch without one
+/// can no longer be restored.
+const MANIFEST_NAME: &str = "manifest.json";
+
+/// Directory of a batch holding the purged paths themselves.
+const ITEMS_DIR_NAME: &str = "items";
+
+/// How long purged caches stay restorable before they are deleted.
+pub const UNDO_GRACE_PERIOD: Duration = Duration::from_secs(5 * 60);
+
+/// Share of the filesystem below which free space counts as low, and the trash
+/// is emptied without waiting for the grace period.
+const LOW_SPACE_RATIO: f64 = 0.1;
+
+/// A path moved to the trash by a purge.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TrashedItem {
+    /// Where the path was before the purge.
+    original: PathBuf,
+    /// File name inside the batch's items directory.
+    name: String,
+}
+
+/// What a batch's manifest records.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    /// Unix timestamp of the purge.
+    created: u64,
+    items: Vec<TrashedItem>,
+    /// Target links the purge removed or pointed at an empty cache, put back
+    /// by an undo.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    links: Vec<SettledLink>,
+}
+
+/// The paths moved to the trash of one target root by a purge.
+struct TrashBatch {
+    dir: PathBuf,
+    manifest: Manifest,
+}
+
+impl TrashBatch {
+    /// Creates an empty batch in the trash of a target root.
+    fn create(target_root: &Path, name: &str) -> io::Result<Self> {
+        let dir = target_root.join(TRASH_DIR_NAME).join(name);
+        fs::create_dir_all(dir.join(ITEMS_DIR_NAME))?;
+
+        let batch = TrashBatch {
+            dir,
+            manifest: Manifest {
+                created: unix_now(),
+                items: Vec::new(),
+                links: Vec::new(),
+            },
+        };
+        batch.save()?;
+        Ok(batch)
+    }
+
+    /// Writes the manifest, replacing it atomically.
+    fn save(&self) -> io::Result<()> {
+        let path = self.dir.join(MANIFEST_NAME);
+        let tmp_path = self.dir.join(format!("{}.tmp", MANIFEST_NAME));
+
+        fs::write(&tmp_path, serde_json::to_vec_pretty(&self.manifest)?)?;
+        fs::rename(&tmp_path, &path)
+    }
+
+    /// Moves a path into the batch.
+    ///
+    /// Returns false, leaving the path in place, if it is on another
+    /// filesystem than the trash and cannot be renamed into it.
+    fn add(&mut self, path: &Path) -> io::Result<bool> {
+        let name = self.manifest.items.len().to_string();
+        match fs::rename(path, self.dir.join(ITEMS_DIR_NAME).join(&name)) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => return Ok(false),
+            Err(e) => return Err(e),
+        }
+
+        self.manifest.items.push(TrashedItem {
+            original: path.to_path_buf(),
+            name,
+        });
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Hands the batch over to a detached process deleting it once the grace
+    /// period is over, or right away when space is low.
+    ///
+    /// Returns how long the batch stays restorable, or `None` for an empty
+    /// batch, which is removed immediately.
+    fn finish(self, target_root: &Path) -> io::Result<Option<Duration>> {
+        let grace = if is_space_low(target_root) {
+            Duration::ZERO
+        } else {
+            UNDO_GRACE_PERIOD
+        };
+        self.finish_after(grace)
+    }
+
+    /// Hands the batch over to a detached process deleting it after `grace`.
+    fn finish_after(self, grace: Duration) -> io::Result<Option<Duration>> {
+        if self.manifest.items.is_empty() {
+            fs::remove_dir_all(&self.dir)?;
+            return Ok(None);
+        }
+
+        spawn_deleter(&self.dir, grace)?;
+        Ok(Some(grace))
+    }
+}
+
+/// The paths moved to the trash by one purge, in a batch per target root.
+///
+/// The batches share a name, so `rcargo purge --undo` restores them together.
+pub struct PurgeTrash {
+    name: String,
+    batches: Vec<(PathBuf, TrashBatch)>,
+}
+
+impl Default for PurgeTrash {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PurgeTrash {
+    pub fn new() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_nanos();
+        PurgeTrash {
+            // Zero-padded so batch names sort by creation time
+            name: format!("{:020}-{}", nanos, process::id()),
+            batches: Vec::new(),
+        }
+    }
+
+    /// Moves a path into the batch of its target root, creating the batch on
+    /// first use.
+    ///
+    /// Returns false, leaving the path in place, if it is on another
+    /// filesystem than the trash and cannot be renamed into it.
+    pub fn add(&mut self, target_root: &Path, path: &Path) -> io::Result<bool> {
+        let index = match self
+            .batches
+            .iter()
+            .position(|(root, _)| root == target_root)
+        {
+            Some(index) => index,
+            None => {
+                let batch = TrashBatch::create(target_root, &self.name)?;
+                self.batches.push((target_root.to_path_buf(), batch));
+                self.batches.len() - 1
+            }
+        };
+        self.batches[index].1.add(path)
+    }
+
+    /// Records the target links settled after the purge, so an undo can put
+    /// them back.
+    pub fn record_links(&mut self, links: &[SettledLink]) -> io::Result<()> {
+        // The batches are restored together, one of them is enough
+        match self.batches.first_mut() {
+            Some((_, batch)) if !links.is_empty() => {
+                batch.manifest.links.extend_from_slice(links);
+                batch.save()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Hands every batch over to a detached deleter.
+    ///
+    /// Returns how long the whole purge stays restorable, or `None` if nothing
+    /// was trashed.
+    pub fn finish(self) -> io::Result<Option<Duration>> {
+        let mut window: Option<Duration> = None;
+        for (target_root, batch) in self.batches {
+            if let Some(grace) = batch.finish(&target_root)? {
+                window = Some(window.map_or(grace, |window| window.min(grace)));
+            }
+        }
+        Ok(window)
+    }
+
+    /// Hands every batch over to a detached deleter that starts right away,
+    /// for caches removed to make room rather than at the user's request.
+    pub fn finish_now(self) -> io::Result<()> {
+        for (_, batch) in self.batches {
+            batch.finish_after(Duration::ZERO)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks whether the filesystem holding a target root is running out of space.
+fn is_space_low(target_root: &Path) -> bool {
+    filesystem_space(target_root).is_ok_and(|space| {
+        space.total > 0 && (space.available as f64) < space.total as f64 * LOW_SPACE_RATIO
+    })
+}
+
+/// Starts `rcargo delete-trash` for a batch in a detached process, so the
+/// deletion neither blocks the terminal nor dies with it.
+fn spawn_deleter(batch_dir: &Path, grace: Duration) -> io::Result<()> {
+    spawn_detached([
+        OsStr::new("delete-trash"),
+        OsStr::new("--grace"),
+        OsStr::new(&grace.as_secs().to_string()),
+        batch_dir.as_os_str(),
+    ])
+}
+
+/// Checks that a directory is a batch in the trash of one of the target roots,
+/// so `rcargo delete-trash` cannot be pointed at anything else.
+fn is_trash_batch(batch_dir: &Path) -> bool {
+    let Some(parent) = batch_dir
+        .parent()
+        .and_then(|parent| fs::canonicalize(parent).ok())
+    else {
+        return false;
+    };
+    target_roots().is_ok_and(|roots| {
+        roots.iter().any(|root| {
+            fs::canonicalize(root.path.join(TRASH_DIR_NAME)).is_ok_and(|trash| trash == parent)
+        })
+    })
+}
+
+/// Deletes a batch after waiting for `grace`, unless it was restored meanwhile.
+pub fn delete_batch(batch_dir: &Path, grace: Duration) -> io::Result<()> {
+    if !is_trash_batch(batch_dir) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "'{}' is not in the trash of a target root",
+                batch_dir.display()
+            ),
+        ));
+    }
+
+    thread::sleep(grace);
+
+    let manifest_path = batch_dir.join(MANIFEST_NAME);
+    let manifest = match File::open(&manifest_path) {
+        Ok(manifest) => manifest,
+        // Either restored, or left over from a deletion cut short
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return remove_batch_dir(batch_dir),
+        Err(e) => return Err(e),
+    };
+    // Held until the batch is gone, so an undo cannot restore half of it
+    manifest.lock()?;
+
+    // Restored by an undo while waiting for the lock
+    if !manifest_path.exists() {
+        return Ok(());
+    }
+
+    fs::remove_file(&manifest_path)?;
+    remove_batch_dir(batch_dir)
+}
+
+/// Removes what is left of a batch, if anything.
+fn remove_batch_dir(batch_dir: &Path) -> io::Result<()> {
+    match fs::remove_dir_all(batch_dir) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Lists the batches in the trash of a target root, oldest first.
+fn list_batches(target_root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut batches = Vec::new();
+    match fs::read_dir(target_root.join(TRASH_DIR_NAME)) {
+        Ok(entries) => {
+            for entry in entries {
+                let entry = entry?;
+                if entry.file_type()?.is_dir() {
+                    batches.push(entry.path());
+                }
+            }
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    batches.sort();
+    Ok(batches)
+}
+
+/// Outcome of restoring one trashed path.
+#[derive(Debug)]
+pub enum RestoreOutcome {
+    Restored,
+    /// Something other than an empty directory took the path's place.
+    Occupied,
+    Failed(io::Error),
+}
+
+/// A trashed path `rcargo purge --undo` tried to restore.
+#[derive(Debug)]
+pub struct RestoredItem {
+    pub original: PathBuf,
+    pub outcome: RestoreOutcome,
+}
+
+/// What `rcargo purge --undo` put back.
+#[derive(Debug)]
+pub struct RestoredPurge {
+    pub items: Vec<RestoredItem>,
+    /// Target links recreated for the restored caches.
+    pub relinked: Vec<PathBuf>,
+}
+
+/// Restores the most recent purge still in the trash of any target root,
+/// returning what happened to each of its paths.
+///
+/// Paths whose place was taken stay in the trash and are deleted as usual.
+pub fn restore_latest_purge(
+    target_roots: &[&Path],
+) -> Result<Option<RestoredPurge>, Box<dyn std::error::Error>> {
+    let mut restorable = Vec::new();
+    for target_root in target_roots {
+        for batch in list_batches(target_root)? {
+            if batch.join(MANIFEST_NAME).is_file() {
+                restorable.push(batch);
+            }
+        }
+    }
+    let Some(latest) = restorable
+        .iter()
+        .filter_map(|batch| batch.file_name())
+        .max()
+        .map(|name| name.to_os_string())
+    else {
+        return Ok(None);
+    };
+
+    // Locked up front, so a purge is restored entirely or not at all
+    let mut batches = Vec::new();
+    for batch_dir in restorable
+        .into_iter()
+        .filter(|batch| batch.file_name() == Some(latest.as_os_str()))
+    {
+        let manifest_file = File::open(batch_dir.join(MANIFEST_NAME))?;
+        match manifest_file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err("The most recent purge is already being deleted".into());
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+        batches.push((batch_dir, manifest_file));
+    }
+
+    let mut items = Vec::new();
+    let mut links = Vec::new();
+    for (batch_dir, _manifest_file) in batches {
+        let (batch_items, batch_links) = restore_batch(&batch_dir)?;
+        items.extend(batch_items);
+        links.extend(batch_links);
+    }
+    let relinked = restore_links(&links)?;
+    Ok(Some(RestoredPurge { items, relinked }))
+}
+
+/// Puts back the target links of restored caches and tracks them again.
+///
+/// Returns the links that were recreated; those kept by the purge only need
+/// tracking.
+fn restore_links(links: &[SettledLink]) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut relinked = Vec::new();
+    let mut restored = Vec::new();
+    for settled in links {
+        if !settled.cache_dir.is_dir() {
+            continue;
+        }
+        if !settled.recreated && fs::symlink_metadata(&settled.link).is_err() {
+            symlink_dir(&settled.cache_dir, &settled.link)?;
+            relinked.push(settled.link.clone());
+        }
+        restored.push(settled);
+    }
+
+    if !restored.is_empty() {
+        Registry::update(|registry| {
+            for settled in &restored {
+                let (Some(identifier), Some(project_path)) =
+                    (settled.cache_dir.file_name(), settled.link.parent())
+                else {
+                    continue;
+                };
+                if let Some(record) = registry.projects.get_mut(&*identifier.to_string_lossy()) {
+                    record.track_link(project_path, &settled.cache_dir);
+                }
+            }
+        })?;
+    }
+    Ok(relinked)
+}
+
+/// Restores the paths of a locked batch, returning them with the links the
+/// purge settled.
+fn restore_batch(
+    batch_dir: &Path,
+) -> Result<(Vec<RestoredItem>, Vec<SettledLink>), Box<dyn std::error::Error>> {
+    let manifest_path = batch_dir.join(MANIFEST_NAME);
+    let manifest: Manifest = serde_json::from_slice(&fs::read(&manifest_path)?)?;
+
+    let mut items = Vec::new();
+    let mut remaining = Manifest {
+        created: manifest.created,
+        items: Vec::new(),
+        links: Vec::new(),
+    };
+    for item in manifest.items {
+        let outcome = restore_item(&batch_dir.join(ITEMS_DIR_NAME).join(&item.name), &item);
+        if !matches!(outcome, RestoreOutcome::Restored) {
+            remaining.items.push(item.clone());
+        }
+        items.push(RestoredItem {
+            original: item.original,
+            outcome,
+        });
+    }
+
+    if remaining.items.is_empty() {
+        fs::remove_dir_all(batch_dir)?;
+    } else {
+        // Rewritten in place to keep the locked file; the batch's deleter is
+        // still waiting and takes care of what is left
+        fs::write(&manifest_path, serde_json::to_vec_pretty(&remaining)?)?;
+    }
+
+    Ok((items, manifest.links))
+}
+
+/// Moves a trashed path back, replacing an empty directory left in its place.
+fn restore_item(trashed: &Path, item: &TrashedItem) -> RestoreOutcome {
+    // Links named `target` get an empty cache recreated after a purge
+    if item.original.is_dir() && fs::remove_dir(&item.original).is_err() {
+        return RestoreOutcome::Occupied;
+    }
+    if fs::symlink_metadata(&item.original).is_ok() {
+        return RestoreOutcome::Occupied;
+    }
+
+    match fs::rename(trashed, &item.original) {
+        Ok(()) => RestoreOutcome::Restored,
+        Err(e) => RestoreOutcome::Failed(e),
+    }
+}
+
+/// Hands trashed caches that are no longer needed to detached deleters: every
+/// batch when space is low, otherwise those left behind past their grace
+/// period (e.g. because the machine rebooted before the deleter finished).
+///
+/// Batches being deleted by another process are skipped. Returns the number of
+/// batches handed off.
+pub fn empty_trash(target_root: &Path) -> io::Result<usize> {
+    let batches = list_batches(target_root)?;
+    if batches.is_empty() {
+        return Ok(0);
+    }
+
+    let space_low = is_space_low(target_root);
+    let now = SystemTime::now();
+    let mut handed_off = 0;
+
+    for batch_dir in batches {
+        let expired = fs::metadata(&batch_dir)?
+            .modified()
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_some_and(|age| age > UNDO_GRACE_PERIOD);
+        if !space_low && !expired {
+            continue;
+        }
+
+        // Only probed; the deleter takes the lock itself
+        let manifest_path = batch_dir.join(MANIFEST_NAME);
+        if let Ok(manifest) = File::open(&manifest_path) {
+            match manifest.try_lock() {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => continue,
+                Err(TryLockError::Error(e)) => return Err(e),
+            }
+        }
+        spawn_deleter(&batch_dir, Duration::ZERO)?;
+        handed_off += 1;
+    }
+
+    Ok(handed_off)
+}
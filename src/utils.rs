@@ -1,6 +1,9 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
-use std::{env, fs};
+use std::process::{Command, Stdio};
+use std::{env, fmt, fs, io};
 
 #[cfg(unix)]
 use std::os::unix::fs as unix_fs;
@@ -8,6 +11,13 @@ use std::os::unix::fs as unix_fs;
 #[cfg(windows)]
 use std::os::windows::fs as windows_fs;
 
+/// Binary name cargo looks up on `PATH` when running `cargo rcargo`.
+const CARGO_SUBCOMMAND_BIN: &str = "cargo-rcargo";
+
+/// Default name of the symlink created in project roots when
+/// `RCARGO_TARGET_LINK_NAME` is not set.
+pub const DEFAULT_TARGET_LINK_NAME: &str = "target_rcargo";
+
 /// Structure for parsing Cargo.toml files.
 #[derive(Deserialize)]
 struct CargoToml {
@@ -34,6 +44,22 @@ impl ProjectIdentifier {
 
         Ok(ProjectIdentifier { name, hash })
     }
+
+    /// Parses a cache directory name of the form `{name}-{hash}`.
+    pub fn from_identifier(identifier: &str) -> Option<Self> {
+        let (name, hash) = identifier.rsplit_once('-')?;
+        let is_hash = hash.len() == 7 && hash.chars().all(|c| c.is_ascii_hexdigit());
+
+        if name.is_empty() || !is_hash {
+            return None;
+        }
+
+        Some(ProjectIdentifier {
+            name: name.to_string(),
+            hash: hash.to_string(),
+        })
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -48,6 +74,24 @@ pub fn is_rust_project(project_path: &Path) -> bool {
     project_path.join("Cargo.toml").exists()
 }
 
+/// Lists the project cache directories in a target root.
+///
+/// Hidden entries and plain files hold rcargo's own bookkeeping rather than
+/// project caches, so they are skipped.
+pub fn list_project_caches(target_path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut caches = Vec::new();
+
+    for entry in fs::read_dir(target_path)? {
+        let entry = entry?;
+        let is_hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if !is_hidden && entry.file_type()?.is_dir() {
+            caches.push(entry.path());
+        }
+    }
+
+    Ok(caches)
+}
+
 /// Recursively calculates the total size of a directory.
 pub fn calculate_directory_size(path: &Path) -> Result<u64, Box<dyn std::error::Error>> {
     let mut total_size = 0;
@@ -57,7 +101,8 @@ pub fn calculate_directory_size(path: &Path) -> Result<u64, Box<dyn std::error::
             let entry = entry?;
             let entry_path = entry.path();
 
-            if entry_path.is_dir() {
+            // Symlinked profile directories belong to caches in other roots
+            if entry.file_type()?.is_dir() {
                 total_size += calculate_directory_size(&entry_path)?;
             } else {
                 total_size += entry.metadata()?.len();
@@ -68,6 +113,87 @@ pub fn calculate_directory_size(path: &Path) -> Result<u64, Box<dyn std::error::
     Ok(total_size)
 }
 
+/// Disk usage of a directory tree.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DirectoryUsage {
+    /// Sum of file lengths.
+    pub apparent_bytes: u64,
+    /// Space taken on disk, counting hard-linked files once.
+    pub allocated_bytes: u64,
+    /// Number of files and symlinks.
+    pub file_count: u64,
+    /// Number of directories, including the measured one.
+    pub directory_count: u64,
+}
+
+impl DirectoryUsage {
+    /// Adds another measurement to this one.
+    pub fn add(&mut self, other: DirectoryUsage) {
+        self.apparent_bytes += other.apparent_bytes;
+        self.allocated_bytes += other.allocated_bytes;
+        self.file_count += other.file_count;
+        self.directory_count += other.directory_count;
+    }
+}
+
+/// Measures disk usage without following symlinks, counting each hard-linked
+/// file once across all measurements.
+#[derive(Debug, Default)]
+pub struct UsageMeter {
+    seen_inodes: HashSet<(u64, u64)>,
+}
+
+impl UsageMeter {
+    /// Measures a file or directory tree.
+    pub fn measure(&mut self, path: &Path) -> io::Result<DirectoryUsage> {
+        let mut usage = DirectoryUsage::default();
+        let metadata = fs::symlink_metadata(path)?;
+        if metadata.is_dir() {
+            self.add_directory(path, &mut usage)?;
+        } else {
+            self.add_file(&metadata, &mut usage);
+        }
+        Ok(usage)
+    }
+
+    fn add_directory(&mut self, path: &Path, usage: &mut DirectoryUsage) -> io::Result<()> {
+        usage.directory_count += 1;
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                self.add_directory(&entry.path(), usage)?;
+            } else {
+                self.add_file(&metadata, usage);
+            }
+        }
+        Ok(())
+    }
+
+    fn add_file(&mut self, metadata: &fs::Metadata, usage: &mut DirectoryUsage) {
+        usage.file_count += 1;
+        usage.apparent_bytes += metadata.len();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            // Cargo hard-links final artifacts next to their `deps/` originals
+            if metadata.nlink() <= 1 || self.seen_inodes.insert((metadata.dev(), metadata.ino())) {
+                usage.allocated_bytes += metadata.blocks() * 512;
+            }
+        }
+        #[cfg(not(unix))]
+        {
+            usage.allocated_bytes += metadata.len();
+        }
+    }
+}
+
+/// Measures the disk usage of a directory tree without following symlinks.
+pub fn measure_directory(path: &Path) -> io::Result<DirectoryUsage> {
+    UsageMeter::default().measure(path)
+}
+
 /// Formats a byte size into a human-readable string.
 pub fn format_size(size: u64) -> String {
     const KB: u64 = 1024;
@@ -85,6 +211,376 @@ pub fn format_size(size: u64) -> String {
     }
 }
 
+/// Parses a byte size such as `500MiB`, `1.5G`, `200MB` or `4096`.
+///
+/// Suffixes with an `i` and single letters are binary (`K` = 1024), two-letter
+/// ones like `MB` are decimal.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        "t" | "tib" => 1 << 40,
+        "kb" => 1_000,
+        "mb" => 1_000_000,
+        "gb" => 1_000_000_000,
+        "tb" => 1_000_000_000_000,
+        _ => return Err(format!("invalid size unit in '{}'", value)),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", value))?;
+
+    let bytes = number * multiplier as f64;
+    if bytes >= u64::MAX as f64 {
+        return Err(format!("size '{}' is too large", value));
+    }
+    Ok(bytes as u64)
+}
+
+/// Formats an age in seconds as a rough human-readable string, e.g. `3 days ago`.
+pub fn format_age(seconds: u64) -> String {
+    const MINUTE: u64 = 60;
+    const HOUR: u64 = MINUTE * 60;
+    const DAY: u64 = HOUR * 24;
+
+    let (count, unit) = if seconds >= DAY {
+        (seconds / DAY, "day")
+    } else if seconds >= HOUR {
+        (seconds / HOUR, "hour")
+    } else if seconds >= MINUTE {
+        (seconds / MINUTE, "minute")
+    } else {
+        return "just now".to_string();
+    };
+
+    format!(
+        "{} {}{} ago",
+        count,
+        unit,
+        if count == 1 { "" } else { "s" }
+    )
+}
+
+/// Space on the filesystem holding a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilesystemSpace {
+    /// Bytes available to unprivileged users.
+    pub available: u64,
+    pub total: u64,
+    /// Inodes available to unprivileged users.
+    pub available_inodes: u64,
+    pub total_inodes: u64,
+}
+
+/// Queries the space of the filesystem holding a path.
+#[cfg(unix)]
+pub fn filesystem_space(path: &Path) -> io::Result<FilesystemSpace> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is a valid C string and `stat` is a writable statvfs
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let fragment_size = stat.f_frsize as u64;
+    Ok(FilesystemSpace {
+        available: stat.f_bavail as u64 * fragment_size,
+        total: stat.f_blocks as u64 * fragment_size,
+        available_inodes: stat.f_favail as u64,
+        total_inodes: stat.f_files as u64,
+    })
+}
+
+/// Queries the space of the filesystem holding a path.
+#[cfg(not(unix))]
+pub fn filesystem_space(path: &Path) -> io::Result<FilesystemSpace> {
+    let _ = path;
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Filesystem space queries are not supported on this platform",
+    ))
+}
+
+/// Names the type of the filesystem holding a path, such as `tmpfs` or `ext4`.
+#[cfg(target_os = "linux")]
+pub fn filesystem_type(path: &Path) -> io::Result<String> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is a valid C string and `stat` is a writable statfs
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // Magic numbers from linux/magic.h; `f_type` is signed on some targets
+    let name = match stat.f_type as u64 & 0xffff_ffff {
+        0x0102_1994 => "tmpfs",
+        0x8584_58f6 => "ramfs",
+        0xef53 => "ext4",
+        0x9123_683e => "btrfs",
+        0x5846_5342 => "xfs",
+        0x2fc1_2fc1 => "zfs",
+        0xf2f5_2010 => "f2fs",
+        0xca45_1a4e => "bcachefs",
+        0x794c_7630 => "overlay",
+        0x6969 => "nfs",
+        0xff53_4d42 => "cifs",
+        0x6573_5546 => "fuse",
+        0x4d44 => "vfat",
+        0x2011_bab0 => "exfat",
+        0x5346_544e => "ntfs",
+        magic => return Ok(format!("unknown (0x{:x})", magic)),
+    };
+    Ok(name.to_string())
+}
+
+/// Names the type of the filesystem holding a path, such as `apfs`.
+#[cfg(target_os = "macos")]
+pub fn filesystem_type(path: &Path) -> io::Result<String> {
+    use std::ffi::{CStr, CString};
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat: libc::statfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is a valid C string and `stat` is a writable statfs
+    if unsafe { libc::statfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // SAFETY: the kernel fills `f_fstypename` with a NUL-terminated name
+    let name = unsafe { CStr::from_ptr(stat.f_fstypename.as_ptr()) };
+    Ok(name.to_string_lossy().to_string())
+}
+
+/// Names the type of the filesystem holding a path.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn filesystem_type(path: &Path) -> io::Result<String> {
+    let _ = path;
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Filesystem type queries are not supported on this platform",
+    ))
+}
+
+/// Checks whether a filesystem type keeps its contents in memory.
+pub fn is_ram_backed_filesystem(fs_type: &str) -> bool {
+    matches!(fs_type, "tmpfs" | "ramfs")
+}
+
+/// Checks whether the target root is expected on a RAM-backed filesystem via
+/// `RCARGO_EXPECT_RAM`.
+pub fn is_ram_expected() -> bool {
+    env::var("RCARGO_EXPECT_RAM").is_ok_and(|val| val.eq_ignore_ascii_case("true") || val == "1")
+}
+
+/// How a directory ended up at its new location.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveMethod {
+    /// Renamed in place on the same filesystem.
+    Renamed,
+    /// Copied across filesystems, then removed from the source.
+    Copied,
+}
+
+impl fmt::Display for MoveMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoveMethod::Renamed => write!(f, "moved"),
+            MoveMethod::Copied => write!(f, "copied across filesystems"),
+        }
+    }
+}
+
+/// Moves a directory, copying it when source and destination are on different
+/// filesystems.
+///
+/// Copies preserve modification times so cargo's fingerprints stay fresh and
+/// the moved artifacts are not rebuilt.
+pub fn move_directory(source: &Path, destination: &Path) -> io::Result<MoveMethod> {
+    match fs::rename(source, destination) {
+        Ok(()) => Ok(MoveMethod::Renamed),
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            copy_directory(source, destination)?;
+            fs::remove_dir_all(source)?;
+            Ok(MoveMethod::Copied)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Moves the contents of `source` into `destination`, merging directories
+/// present in both and replacing anything else.
+///
+/// Symlinks in `source` pointing at their counterpart in `destination` are
+/// dropped, and the entries in `keep`, relative to `source`, stay behind.
+pub fn merge_directory(source: &Path, destination: &Path, keep: &[PathBuf]) -> io::Result<()> {
+    merge_entries(source, destination, Path::new(""), keep)
+}
+
+fn merge_entries(
+    source: &Path,
+    destination: &Path,
+    relative: &Path,
+    keep: &[PathBuf],
+) -> io::Result<()> {
+    fs::create_dir_all(destination)?;
+
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let entry_relative = relative.join(entry.file_name());
+        if keep.contains(&entry_relative) {
+            continue;
+        }
+        let entry_path = entry.path();
+        let dest_path = destination.join(entry.file_name());
+        let file_type = entry.file_type()?;
+
+        if file_type.is_symlink() && fs::read_link(&entry_path)? == dest_path {
+            fs::remove_file(&entry_path)?;
+            continue;
+        }
+
+        match fs::symlink_metadata(&dest_path) {
+            Ok(metadata) if metadata.is_dir() && file_type.is_dir() => {
+                merge_entries(&entry_path, &dest_path, &entry_relative, keep)?;
+                if fs::read_dir(&entry_path)?.next().is_none() {
+                    fs::remove_dir(&entry_path)?;
+                }
+                continue;
+            }
+            Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&dest_path)?,
+            Ok(_) => fs::remove_file(&dest_path)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        if file_type.is_dir() {
+            move_directory(&entry_path, &dest_path)?;
+            continue;
+        }
+        match fs::rename(&entry_path, &dest_path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                if file_type.is_symlink() {
+                    copy_symlink(&entry_path, &dest_path)?;
+                } else {
+                    fs::copy(&entry_path, &dest_path)?;
+                    copy_file_times(&entry_path, &dest_path)?;
+                }
+                fs::remove_file(&entry_path)?;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Recursively copies a directory, preserving modification and access times.
+///
+/// Symlinks are recreated rather than followed.
+pub fn copy_directory(source: &Path, destination: &Path) -> io::Result<()> {
+    fs::create_dir(destination)?;
+
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let entry_path = entry.path();
+        let dest_path = destination.join(entry.file_name());
+        let file_type = entry.file_type()?;
+
+        if file_type.is_symlink() {
+            copy_symlink(&entry_path, &dest_path)?;
+        } else if file_type.is_dir() {
+            copy_directory(&entry_path, &dest_path)?;
+        } else {
+            fs::copy(&entry_path, &dest_path)?;
+            copy_file_times(&entry_path, &dest_path)?;
+        }
+    }
+
+    // Set directory times last, since creating entries updates them
+    copy_file_times(source, destination)
+}
+
+/// Recreates a symlink at a new location.
+fn copy_symlink(source: &Path, destination: &Path) -> io::Result<()> {
+    let link_target = fs::read_link(source)?;
+
+    #[cfg(unix)]
+    {
+        unix_fs::symlink(link_target, destination)
+    }
+    #[cfg(windows)]
+    {
+        if source.is_dir() {
+            windows_fs::symlink_dir(link_target, destination)
+        } else {
+            windows_fs::symlink_file(link_target, destination)
+        }
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = (link_target, destination);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "symlinks are not supported on this platform",
+        ))
+    }
+}
+
+/// Creates a symlink to a directory.
+pub fn symlink_dir(target: &Path, link: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        unix_fs::symlink(target, link)
+    }
+    #[cfg(windows)]
+    {
+        windows_fs::symlink_dir(target, link)
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = (target, link);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "symlinks are not supported on this platform",
+        ))
+    }
+}
+
+/// Copies modification and access times from one path to another.
+fn copy_file_times(source: &Path, destination: &Path) -> io::Result<()> {
+    let metadata = fs::metadata(source)?;
+    let mut times = fs::FileTimes::new().set_modified(metadata.modified()?);
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+
+    // On Unix only ownership matters, so a read-only handle also works for
+    // directories and read-only files
+    #[cfg(unix)]
+    let file = fs::File::open(destination)?;
+    #[cfg(not(unix))]
+    let file = fs::OpenOptions::new().write(true).open(destination)?;
+
+    file.set_times(times)
+}
+
 /// Generates a short hash for a project based on its path.
 fn generate_project_hash(project_path: &Path) -> String {
     let project_path_str = project_path.to_string_lossy();
@@ -127,73 +623,149 @@ fn get_project_name(project_path: &Path) -> Result<String, Box<dyn std::error::E
         .to_string())
 }
 
-// Helper function to create the target symlink
-pub fn create_target_symlink(
-    project_path: &Path,
-    cargo_target_dir: &Path,
-) -> Result<(), std::io::Error> {
-    if env::var("RCARGO_NO_TARGET_LINK")
+/// Checks whether target symlink creation is disabled via `RCARGO_NO_TARGET_LINK`.
+pub fn is_target_link_disabled() -> bool {
+    env::var("RCARGO_NO_TARGET_LINK")
         .is_ok_and(|val| val.eq_ignore_ascii_case("true") || val == "1")
-    {
-        return Ok(()); // Skip creating link if RCARGO_NO_TARGET_LINK is set
-    }
+}
 
+/// Gets the name of the target symlink created in project roots.
+pub fn get_target_link_name() -> String {
     let symlink_name_from_env = env::var("RCARGO_TARGET_LINK_NAME").unwrap_or_default();
-    let symlink_name = if symlink_name_from_env.is_empty() {
-        "target_rcargo"
+    if symlink_name_from_env.is_empty() {
+        DEFAULT_TARGET_LINK_NAME.to_string()
     } else {
-        &symlink_name_from_env
+        symlink_name_from_env
+    }
+}
+
+/// State of the target symlink in a project root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum TargetLinkState {
+    /// Link creation is disabled by `RCARGO_NO_TARGET_LINK`.
+    Disabled,
+    /// Nothing exists at the link path yet.
+    Missing,
+    /// The link points at the project's cache directory.
+    Ok,
+    /// The link points at a different directory.
+    PointsElsewhere { target: PathBuf },
+    /// A regular file occupies the link path.
+    BlockedByFile,
+    /// A real directory occupies the link path.
+    BlockedByDirectory,
+    /// Something that is not a file, directory or symlink occupies the link path.
+    BlockedByOther,
+    /// The link path could not be inspected.
+    Unknown { error: String },
+}
+
+impl fmt::Display for TargetLinkState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TargetLinkState::Disabled => write!(f, "disabled"),
+            TargetLinkState::Missing => write!(f, "missing"),
+            TargetLinkState::Ok => write!(f, "ok"),
+            TargetLinkState::PointsElsewhere { target } => {
+                write!(f, "points elsewhere ({})", target.display())
+            }
+            TargetLinkState::BlockedByFile => write!(f, "blocked by a file"),
+            TargetLinkState::BlockedByDirectory => write!(f, "blocked by a directory"),
+            TargetLinkState::BlockedByOther => write!(f, "blocked by a special file"),
+            TargetLinkState::Unknown { error } => write!(f, "unknown ({})", error),
+        }
+    }
+}
+
+/// Inspects the target symlink of a project without modifying anything.
+pub fn inspect_target_symlink(project_path: &Path, cargo_target_dir: &Path) -> TargetLinkState {
+    if is_target_link_disabled() {
+        return TargetLinkState::Disabled;
+    }
+
+    let symlink_path = project_path.join(get_target_link_name());
+    let metadata = match fs::symlink_metadata(&symlink_path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return TargetLinkState::Missing,
+        Err(e) => {
+            return TargetLinkState::Unknown {
+                error: e.to_string(),
+            };
+        }
     };
 
-    let symlink_path = project_path.join(symlink_name);
+    if metadata.file_type().is_symlink() {
+        match fs::read_link(&symlink_path) {
+            Ok(existing_target) if existing_target == cargo_target_dir => TargetLinkState::Ok,
+            Ok(existing_target) => TargetLinkState::PointsElsewhere {
+                target: existing_target,
+            },
+            Err(e) => TargetLinkState::Unknown {
+                error: e.to_string(),
+            },
+        }
+    } else if metadata.is_file() {
+        TargetLinkState::BlockedByFile
+    } else if metadata.is_dir() {
+        TargetLinkState::BlockedByDirectory
+    } else {
+        TargetLinkState::BlockedByOther
+    }
+}
+
+// Helper function to create the target symlink
+pub fn create_target_symlink(
+    project_path: &Path,
+    cargo_target_dir: &Path,
+) -> Result<(), std::io::Error> {
+    let symlink_path = project_path.join(get_target_link_name());
     let mut create_link = true;
 
-    if symlink_path.exists() {
-        match fs::symlink_metadata(&symlink_path) {
-            Ok(metadata) => {
-                if metadata.file_type().is_symlink() {
-                    // If same target directory, do nothing
-                    if let Ok(existing_target) = fs::read_link(&symlink_path)
-                        && existing_target == cargo_target_dir
-                    {
-                        return Ok(());
-                    }
-                    // If symlink exists but points to a different target, remove it
-                    if let Err(e) = fs::remove_file(&symlink_path) {
-                        eprintln!(
-                            "Warning: Failed to remove existing symlink at '{}': {}. Proceeding to create new one.",
-                            symlink_path.display(),
-                            e
-                        );
-                    }
-                } else if metadata.is_file() {
-                    eprintln!(
-                        "Warning: '{}' already exists and is a file. Skipping symlink creation.",
-                        symlink_path.display()
-                    );
-                    create_link = false;
-                } else if metadata.is_dir() {
-                    eprintln!(
-                        "Warning: '{}' already exists and is a directory. Skipping symlink creation.",
-                        symlink_path.display()
-                    );
-                    create_link = false;
-                } else {
-                    eprintln!(
-                        "Warning: '{}' exists and is not a file, directory, or symlink. Skipping symlink creation.",
-                        symlink_path.display()
-                    );
-                    create_link = false;
-                }
-            }
-            Err(e) => {
+    match inspect_target_symlink(project_path, cargo_target_dir) {
+        // Skip creating link if RCARGO_NO_TARGET_LINK is set
+        TargetLinkState::Disabled => return Ok(()),
+        // If same target directory, do nothing
+        TargetLinkState::Ok => return Ok(()),
+        TargetLinkState::Missing => {}
+        TargetLinkState::PointsElsewhere { .. } => {
+            // If symlink exists but points to a different target, remove it
+            if let Err(e) = fs::remove_file(&symlink_path) {
                 eprintln!(
-                    "Warning: Failed to get metadata for '{}': {}. Attempting to create symlink anyway.",
+                    "Warning: Failed to remove existing symlink at '{}': {}. Proceeding to create new one.",
                     symlink_path.display(),
                     e
                 );
             }
         }
+        TargetLinkState::BlockedByFile => {
+            eprintln!(
+                "Warning: '{}' already exists and is a file. Skipping symlink creation.",
+                symlink_path.display()
+            );
+            create_link = false;
+        }
+        TargetLinkState::BlockedByDirectory => {
+            eprintln!(
+                "Warning: '{}' already exists and is a directory. Skipping symlink creation.",
+                symlink_path.display()
+            );
+            create_link = false;
+        }
+        TargetLinkState::BlockedByOther => {
+            eprintln!(
+                "Warning: '{}' exists and is not a file, directory, or symlink. Skipping symlink creation.",
+                symlink_path.display()
+            );
+            create_link = false;
+        }
+        TargetLinkState::Unknown { error } => {
+            eprintln!(
+                "Warning: Failed to get metadata for '{}': {}. Attempting to create symlink anyway.",
+                symlink_path.display(),
+                error
+            );
+        }
     }
 
     if create_link {
@@ -244,20 +816,78 @@ pub fn create_target_symlink(
     Ok(())
 }
 
-/// Checks if the given cargo command requires target directory creation.
+/// Outcome of classifying cargo arguments, recording why a command is or isn't
+/// redirected to a cached target directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgsClassification {
+    /// No arguments were given at all.
+    Empty,
+    /// A help/version/list flag or command was found.
+    Informational(String),
+    /// The subcommand compiles or otherwise uses the target directory.
+    BuildCommand(String),
+    /// The subcommand does not use the target directory.
+    OtherCommand(String),
+    /// Only flags were given, so no subcommand could be found.
+    NoSubcommand,
+}
+
+impl ArgsClassification {
+    /// Whether the command needs a redirected target directory.
+    pub fn requires_target_dir(&self) -> bool {
+        matches!(self, ArgsClassification::BuildCommand(_))
+    }
+}
+
+impl fmt::Display for ArgsClassification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArgsClassification::Empty => write!(f, "no cargo arguments given"),
+            ArgsClassification::Informational(arg) => {
+                write!(f, "`{}` only prints information", arg)
+            }
+            ArgsClassification::BuildCommand(cmd) => {
+                write!(f, "`{}` uses the target directory", cmd)
+            }
+            ArgsClassification::OtherCommand(cmd) => {
+                write!(f, "`{}` does not use the target directory", cmd)
+            }
+            ArgsClassification::NoSubcommand => write!(f, "no cargo subcommand found"),
+        }
+    }
+}
+
+/// Global cargo options that take their value as a separate argument.
+const OPTIONS_WITH_VALUE: [&str; 4] = ["--color", "--config", "-C", "-Z"];
+
+/// Finds the cargo subcommand, skipping flags, the values of global options
+/// and rustup `+toolchain` overrides.
+fn find_subcommand(args: &[String]) -> Option<&String> {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if OPTIONS_WITH_VALUE.contains(&arg.as_str()) {
+            iter.next();
+        } else if !arg.starts_with('-') && !arg.starts_with('+') {
+            return Some(arg);
+        }
+    }
+    None
+}
+
+/// Classifies cargo arguments by whether they require target directory creation.
 ///
-/// Returns true only for commands that involve compilation/building and need
-/// a target directory, using a whitelist approach for better safety.
-pub fn is_required_target_dir(args: &[String]) -> bool {
+/// Only commands that involve compilation/building and need a target directory
+/// are redirected, using a whitelist approach for better safety.
+pub fn classify_cargo_args(args: &[String]) -> ArgsClassification {
     if args.is_empty() {
-        return false;
+        return ArgsClassification::Empty;
     }
 
     // Check for global help/version flags first
     for arg in args {
         match arg.as_str() {
             "--help" | "-h" | "--version" | "-V" | "--list" | "help" | "version" => {
-                return false;
+                return ArgsClassification::Informational(arg.clone());
             }
             _ => {}
         }
@@ -284,18 +914,216 @@ pub fn is_required_target_dir(args: &[String]) -> bool {
         "tarpaulin", // if tarpaulin is installed
     ];
 
-    // Find the actual subcommand (skip flags)
-    let subcommand = args.iter().find(|arg| !arg.starts_with('-'));
+    match find_subcommand(args) {
+        Some(cmd) if build_commands.contains(&cmd.as_str()) => {
+            ArgsClassification::BuildCommand(cmd.clone())
+        }
+        Some(cmd) => ArgsClassification::OtherCommand(cmd.clone()),
+        // If no clear subcommand found, default to no redirection for safety
+        None => ArgsClassification::NoSubcommand,
+    }
+}
+
+/// Finds the cargo profile a command builds with: the one named by
+/// `--profile`, `release` for `--release`, otherwise the default of its
+/// subcommand.
+pub fn build_profile(args: &[String]) -> String {
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            // Everything after `--` goes to the built program
+            "--" => break,
+            "--release" | "-r" => return "release".to_string(),
+            "--profile" => {
+                if let Some(profile) = iter.next() {
+                    return profile.clone();
+                }
+            }
+            _ => {
+                if let Some(profile) = arg.strip_prefix("--profile=") {
+                    return profile.to_string();
+                }
+            }
+        }
+    }
+
+    match find_subcommand(args).map(String::as_str) {
+        Some("bench") => "bench".to_string(),
+        Some("install") => "release".to_string(),
+        _ => "dev".to_string(),
+    }
+}
 
-    if let Some(cmd) = subcommand {
-        return build_commands.contains(&cmd.as_str());
+/// Gets the directory cargo names after a profile: `debug` for dev and test,
+/// `release` for release and bench, otherwise the profile name itself.
+pub fn profile_dir_name(profile: &str) -> &str {
+    match profile {
+        "dev" | "test" => "debug",
+        "release" | "bench" => "release",
+        custom => custom,
     }
+}
 
-    // If no clear subcommand found, default to false for safety
-    false
+/// Finds the target triples a command builds for with `--target`, empty for
+/// host builds.
+pub fn build_targets(args: &[String]) -> Vec<String> {
+    let mut targets = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--" => break,
+            "--target" => targets.extend(iter.next().cloned()),
+            _ => {
+                if let Some(target) = arg.strip_prefix("--target=") {
+                    targets.push(target.to_string());
+                }
+            }
+        }
+    }
+    targets
+}
+
+/// Checks whether rcargo was started by cargo as the `cargo rcargo` subcommand.
+///
+/// Cargo runs external subcommands as `cargo-<name> <name> [args...]`, so both
+/// the binary name and the injected first argument are checked.
+pub fn is_cargo_subcommand() -> bool {
+    let mut args = env::args_os();
+    let invoked_as_subcommand = args.next().map(PathBuf::from).is_some_and(|argv0| {
+        argv0
+            .file_stem()
+            .is_some_and(|stem| stem == CARGO_SUBCOMMAND_BIN)
+    });
+
+    invoked_as_subcommand && args.next().is_some_and(|arg| arg == "rcargo")
+}
+
+/// Runs rcargo with the given arguments in a detached process, so the work
+/// neither blocks the terminal nor dies with it.
+pub fn spawn_detached<I, S>(args: I) -> io::Result<()>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut cmd = Command::new(env::current_exe()?);
+    // The `cargo-rcargo` binary expects the argument cargo passes it
+    if is_cargo_subcommand() {
+        cmd.arg("rcargo");
+    }
+    cmd.args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        cmd.process_group(0);
+    }
+
+    cmd.spawn()?;
+    Ok(())
+}
+
+/// Gets the workspace root reported by `cargo metadata` for the current directory.
+///
+/// Returns `None` when cargo cannot describe the current directory (e.g. it is not
+/// inside a cargo project), in which case callers fall back to the current directory.
+pub fn resolve_workspace_root(
+    cargo_path: &str,
+) -> Result<Option<PathBuf>, Box<dyn std::error::Error>> {
+    let mut cmd = Command::new(cargo_path);
+    cmd.args("metadata --format-version 1 --no-deps".split_whitespace());
+    let output = cmd.output()?;
+
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    // Parse the JSON output to get the project path
+    let metadata: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    Ok(Some(PathBuf::from(
+        metadata["workspace_root"].as_str().unwrap_or("."),
+    )))
+}
+
+/// A project resolved from the current directory.
+#[derive(Debug, Clone)]
+pub struct ResolvedProject {
+    pub path: PathBuf,
+    /// Whether `path` is the workspace root reported by `cargo metadata`
+    /// rather than the current directory fallback.
+    pub from_cargo_metadata: bool,
+    pub identifier: ProjectIdentifier,
+}
+
+/// Resolves the project rcargo maps the current directory to.
+pub fn resolve_project(cargo_path: &str) -> Result<ResolvedProject, Box<dyn std::error::Error>> {
+    // Fall back to the current directory if the metadata command fails
+    let (path, from_cargo_metadata) = match resolve_workspace_root(cargo_path)? {
+        Some(workspace_root) => (workspace_root, true),
+        None => (env::current_dir()?, false),
+    };
+    let identifier = ProjectIdentifier::new(&path)?;
+
+    Ok(ResolvedProject {
+        path,
+        from_cargo_metadata,
+        identifier,
+    })
+}
+
+/// Matches text against a shell-style glob supporting `*` and `?`.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    // Greedy matching, backtracking to the most recent `*`
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            p = star_p + 1;
+            t = star_t + 1;
+            backtrack = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Quotes an argument for display in a POSIX shell command line.
+pub fn shell_quote(arg: &str) -> String {
+    let is_plain = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+
+    if is_plain {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
 }
 
 pub fn get_cargo_path() -> String {
+    // When running as `cargo rcargo`, use the cargo binary that invoked us
+    if is_cargo_subcommand()
+        && let Ok(cargo_path) = env::var("CARGO")
+        && !cargo_path.trim().is_empty()
+    {
+        return cargo_path.trim().to_string();
+    }
+
     // Check if RCARGO_CARGO_PATH environment variable is set, otherwise use "/usr/bin/cargo", else use the default cargo path.
     let cargo_path = env::var("RCARGO_CARGO_PATH")
         .unwrap_or_else(|_| "/usr/bin/cargo".to_string())
@@ -307,3 +1135,198 @@ pub fn get_cargo_path() -> String {
     }
     cargo_path
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn identifier_round_trips() {
+        let original = ProjectIdentifier {
+            name: "app".to_string(),
+            hash: generate_project_hash(Path::new("/work/app")),
+        };
+        let parsed = ProjectIdentifier::from_identifier(&original.identifier()).unwrap();
+        assert_eq!(parsed.name, original.name);
+        assert_eq!(parsed.hash, original.hash);
+        assert_eq!(parsed.identifier(), original.identifier());
+    }
+
+    #[test]
+    fn identifier_keeps_separators_in_the_name() {
+        let parsed = ProjectIdentifier::from_identifier("my-cool-app-0a1b2c3").unwrap();
+        assert_eq!(parsed.name(), "my-cool-app");
+        assert_eq!(parsed.hash, "0a1b2c3");
+
+        let original = ProjectIdentifier {
+            name: "tool-2".to_string(),
+            hash: generate_project_hash(Path::new("/work/tool-2")),
+        };
+        let parsed = ProjectIdentifier::from_identifier(&original.identifier()).unwrap();
+        assert_eq!(parsed.name(), "tool-2");
+    }
+
+    #[test]
+    fn identifier_rejects_other_names() {
+        assert!(ProjectIdentifier::from_identifier("app").is_none());
+        assert!(ProjectIdentifier::from_identifier("app-123456").is_none());
+        assert!(ProjectIdentifier::from_identifier("app-12345678").is_none());
+        assert!(ProjectIdentifier::from_identifier("app-123456z").is_none());
+        assert!(ProjectIdentifier::from_identifier("-1234567").is_none());
+        assert!(ProjectIdentifier::from_identifier(".trash").is_none());
+    }
+
+    #[test]
+    fn sizes_read_binary_and_decimal_units() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("512b"), Ok(512));
+        assert_eq!(parse_size("2k"), Ok(2048));
+        assert_eq!(parse_size("2KiB"), Ok(2048));
+        assert_eq!(parse_size("3m"), Ok(3 << 20));
+        assert_eq!(parse_size("20G"), Ok(20 << 30));
+        assert_eq!(parse_size("1tib"), Ok(1 << 40));
+        assert_eq!(parse_size("2kb"), Ok(2_000));
+        assert_eq!(parse_size("3MB"), Ok(3_000_000));
+        assert_eq!(parse_size("20GB"), Ok(20_000_000_000));
+        assert_eq!(parse_size("1Tb"), Ok(1_000_000_000_000));
+    }
+
+    #[test]
+    fn sizes_ignore_case_and_whitespace() {
+        assert_eq!(parse_size("5gib"), parse_size("5GiB"));
+        assert_eq!(parse_size("5GIB"), Ok(5 << 30));
+        assert_eq!(parse_size(" 5 G "), Ok(5 << 30));
+    }
+
+    #[test]
+    fn sizes_accept_decimals() {
+        assert_eq!(parse_size("1.5k"), Ok(1536));
+        assert_eq!(parse_size("0.5G"), Ok(512 << 20));
+        assert_eq!(parse_size("2.5GB"), Ok(2_500_000_000));
+        assert_eq!(parse_size(".5m"), Ok(512 << 10));
+    }
+
+    #[test]
+    fn sizes_reject_overflow() {
+        assert!(parse_size("16777216TiB").is_err());
+        assert!(parse_size("99999999999999999999").is_err());
+        assert_eq!(parse_size("16777215TiB"), Ok(16777215 << 40));
+    }
+
+    #[test]
+    fn sizes_reject_bad_input() {
+        for value in ["", "G", "abc", "1.2.3G", "-5G", "5 GiBs", "5x", "1e3"] {
+            assert!(parse_size(value).is_err(), "{value}");
+        }
+    }
+
+    #[test]
+    fn glob_matches_literal_text() {
+        assert!(glob_match("my-app", "my-app"));
+        assert!(!glob_match("my-app", "my-app2"));
+        assert!(!glob_match("my-app", "my-ap"));
+        assert!(glob_match("", ""));
+    }
+
+    #[test]
+    fn glob_matches_wildcards() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("my-*", "my-app"));
+        assert!(glob_match("*-app", "my-app"));
+        assert!(glob_match("m?-app", "my-app"));
+        assert!(!glob_match("m?-app", "m-app"));
+        assert!(glob_match("/home/*/work/*", "/home/user/work/big/app"));
+        assert!(!glob_match("/home/*/work", "/home/user/play"));
+    }
+
+    #[test]
+    fn glob_backtracks_over_stars() {
+        assert!(glob_match("*ab*ab", "xabyabab"));
+        assert!(glob_match("a*b*c", "abbbc"));
+        assert!(!glob_match("a*b*c", "abbbd"));
+        assert!(glob_match("**", "abc"));
+    }
+
+    #[test]
+    fn build_profile_defaults_to_dev() {
+        assert_eq!(build_profile(&args("build")), "dev");
+        assert_eq!(build_profile(&args("test --workspace")), "dev");
+        assert_eq!(build_profile(&[]), "dev");
+    }
+
+    #[test]
+    fn build_profile_reads_release_and_profile_flags() {
+        assert_eq!(build_profile(&args("build --release")), "release");
+        assert_eq!(build_profile(&args("build -r")), "release");
+        assert_eq!(build_profile(&args("build --profile ci")), "ci");
+        assert_eq!(build_profile(&args("build --profile=ci")), "ci");
+    }
+
+    #[test]
+    fn build_profile_follows_the_subcommand() {
+        assert_eq!(build_profile(&args("bench")), "bench");
+        assert_eq!(build_profile(&args("install ripgrep")), "release");
+        assert_eq!(build_profile(&args("+nightly bench")), "bench");
+        assert_eq!(build_profile(&args("--color always bench")), "bench");
+        assert_eq!(
+            build_profile(&args("-C sub --config x=1 -Z foo install")),
+            "release"
+        );
+    }
+
+    #[test]
+    fn build_profile_ignores_program_arguments() {
+        assert_eq!(build_profile(&args("run -- --release")), "dev");
+        assert_eq!(build_profile(&args("run -- --profile ci")), "dev");
+    }
+
+    #[test]
+    fn classification_skips_option_values() {
+        assert_eq!(
+            classify_cargo_args(&args("--color always build")),
+            ArgsClassification::BuildCommand("build".to_string())
+        );
+        assert_eq!(
+            classify_cargo_args(&args("+nightly --config build.jobs=2 check")),
+            ArgsClassification::BuildCommand("check".to_string())
+        );
+        assert_eq!(
+            classify_cargo_args(&args("--color=always tree")),
+            ArgsClassification::OtherCommand("tree".to_string())
+        );
+        assert_eq!(
+            classify_cargo_args(&args("--color always")),
+            ArgsClassification::NoSubcommand
+        );
+    }
+
+    #[test]
+    fn classification_skips_unstable_flags_after_a_toolchain() {
+        assert_eq!(
+            classify_cargo_args(&args("+nightly -Z foo build")),
+            ArgsClassification::BuildCommand("build".to_string())
+        );
+        assert_eq!(build_profile(&args("+nightly -Z foo bench")), "bench");
+    }
+
+    #[test]
+    fn classification_skips_config_values() {
+        assert_eq!(
+            classify_cargo_args(&args("--config k=v build")),
+            ArgsClassification::BuildCommand("build".to_string())
+        );
+        assert_eq!(
+            classify_cargo_args(&args("--config=k=v build")),
+            ArgsClassification::BuildCommand("build".to_string())
+        );
+        assert_eq!(
+            build_profile(&args("--config k=v install ripgrep")),
+            "release"
+        );
+    }
+}